@@ -349,6 +349,7 @@ Wants=network-online.target
 [Service]
 Type=simple
 ExecStart=$INSTALL_DIR/$BINARY_NAME service -c $CONFIG_DIR/config.toml
+ExecReload=/bin/kill -HUP \\\$MAINPID
 Restart=on-failure
 RestartSec=10
 StandardOutput=journal
@@ -1,38 +1,122 @@
 use anyhow::{Context, Result};
-use cloudflare::endpoints::dns::dns::{
-    CreateDnsRecord, CreateDnsRecordParams, DnsContent, DnsRecord, ListDnsRecords,
-    ListDnsRecordsParams, UpdateDnsRecord, UpdateDnsRecordParams,
-};
+use chrono::{SecondsFormat, Utc};
+use cloudflare::endpoints::dns::dns::DeleteDnsRecord;
 use cloudflare::endpoints::zones::zone::{ListZones, ListZonesParams};
 use cloudflare::framework::auth::Credentials;
 use cloudflare::framework::client::async_api::Client;
 use cloudflare::framework::client::ClientConfig;
+use cloudflare::framework::endpoint::spec::EndpointSpec;
+use cloudflare::framework::endpoint::{serialize_query, Method, RequestBody};
+use cloudflare::framework::response::{
+    ApiFailure, ApiResponse, ApiResult, ApiSuccess, ResponseConverter,
+};
 use cloudflare::framework::Environment;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
 use std::net::IpAddr;
+use std::time::Duration;
 use tracing::{debug, info, warn};
 
 use crate::config::{RecordConfig, RecordType};
+use crate::provider::UpdateResult;
+
+/// Marker written into the comment of records managed by cddns, followed by "/<owner_id>"
+pub const MANAGED_MARKER: &str = "managed-by: cddns";
+
+/// Separator between the parts of a record comment
+const COMMENT_SEPARATOR: &str = " | ";
+
+/// Prefix of the last-update timestamp in a record comment
+const UPDATED_PREFIX: &str = "updated: ";
+
+/// Page size for list requests
+const PER_PAGE: u32 = 100;
+
+/// Pause before retrying a rate-limited request; it doubles with every retry
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Retries of a rate-limited request before its error is returned
+const RATE_LIMIT_RETRIES: u32 = 4;
 
 /// Cloudflare DDNS client wrapper
 pub struct DdnsClient {
-    client: Client,
+    client: ApiClient,
+    mark_managed: bool,
+    owner_id: String,
 }
 
 impl DdnsClient {
     /// Create a new DDNS client with the given API token
     pub fn new(api_token: &str) -> Result<Self> {
-        let credentials = Credentials::UserAuthToken {
-            token: api_token.to_string(),
+        Ok(Self {
+            client: api_client(api_token)?,
+            mark_managed: false,
+            owner_id: String::new(),
+        })
+    }
+
+    /// Stamp written records with this instance's ownership marker and update time
+    pub fn with_managed_marker(mut self, mark_managed: bool, owner_id: &str) -> Self {
+        self.mark_managed = mark_managed;
+        self.owner_id = owner_id.to_string();
+        self
+    }
+
+    /// Check that the API token is valid and active
+    ///
+    /// Account-owned tokens are rejected by the user endpoint; for those a
+    /// successful zone listing shows that the token works.
+    pub async fn verify_token(&self) -> Result<()> {
+        let response = match self.client.request(&VerifyToken).await {
+            Ok(response) => response,
+            Err(e) => {
+                let endpoint = ListZones {
+                    params: ListZonesParams {
+                        per_page: Some(1),
+                        ..Default::default()
+                    },
+                };
+                return match self.client.request(&endpoint).await {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err(e).context("Failed to verify API token"),
+                };
+            }
         };
 
-        let client = Client::new(
-            credentials,
-            ClientConfig::default(),
-            Environment::Production,
-        )
-        .context("Failed to create Cloudflare client")?;
+        if response.result.status != "active" {
+            anyhow::bail!("API token is {}", response.result.status);
+        }
+        Ok(())
+    }
 
-        Ok(Self { client })
+    /// List the names of every zone the token can access
+    pub async fn list_zones(&self) -> Result<Vec<String>> {
+        let mut zones = Vec::new();
+        let mut page = 1;
+
+        loop {
+            let endpoint = ListZones {
+                params: ListZonesParams {
+                    page: Some(page),
+                    per_page: Some(PER_PAGE),
+                    ..Default::default()
+                },
+            };
+
+            let response = self
+                .client
+                .request(&endpoint)
+                .await
+                .context("Failed to list zones")?;
+
+            zones.extend(response.result.into_iter().map(|zone| zone.name));
+            if page >= total_pages(&response.result_info) {
+                break;
+            }
+            page += 1;
+        }
+
+        Ok(zones)
     }
 
     /// Get the zone ID for a given zone name
@@ -62,6 +146,54 @@ impl DdnsClient {
         Ok(zone.id)
     }
 
+    /// List every DNS record in a zone
+    pub async fn list_records(&self, zone_id: &str) -> Result<Vec<DnsRecord>> {
+        let mut records = Vec::new();
+        let mut page = 1;
+
+        loop {
+            let endpoint = ListDnsRecords {
+                zone_identifier: zone_id,
+                params: ListDnsRecordsParams {
+                    page: Some(page),
+                    per_page: Some(PER_PAGE),
+                    ..Default::default()
+                },
+            };
+
+            let response = self
+                .client
+                .request(&endpoint)
+                .await
+                .context("Failed to list DNS records")?;
+
+            records.extend(response.result.0);
+            if page >= total_pages(&response.result_info) {
+                break;
+            }
+            page += 1;
+        }
+
+        debug!("Found {} records in zone {}", records.len(), zone_id);
+        Ok(records)
+    }
+
+    /// Delete a DNS record
+    pub async fn delete_record(&self, zone_id: &str, record: &DnsRecord) -> Result<()> {
+        let endpoint = DeleteDnsRecord {
+            zone_identifier: zone_id,
+            identifier: &record.id,
+        };
+
+        self.client
+            .request(&endpoint)
+            .await
+            .context("Failed to delete DNS record")?;
+
+        info!("Deleted {} {}", record.record_type, record.name);
+        Ok(())
+    }
+
     /// Find an existing DNS record by name and type
     pub async fn find_record(
         &self,
@@ -78,6 +210,7 @@ impl DdnsClient {
             zone_identifier: zone_id,
             params: ListDnsRecordsParams {
                 name: Some(record_name.to_string()),
+                record_type: Some(record_type.to_string()),
                 ..Default::default()
             },
         };
@@ -89,15 +222,12 @@ impl DdnsClient {
             .context("Failed to list DNS records")?;
 
         // Filter by record type
-        let record = response.result.into_iter().find(|r| {
-            matches!(
-                (&r.content, record_type),
-                (DnsContent::A { .. }, RecordType::A) | (DnsContent::AAAA { .. }, RecordType::AAAA)
-            ) && r.name == record_name
+        let record = response.result.0.into_iter().find(|r| {
+            r.record_type.eq_ignore_ascii_case(&record_type.to_string()) && r.name == record_name
         });
 
         if let Some(ref r) = record {
-            debug!("Found existing record: {} -> {:?}", r.name, r.content);
+            debug!("Found existing record: {} -> {}", r.name, r.content);
         } else {
             debug!(
                 "No existing {} record found for {}",
@@ -108,29 +238,23 @@ impl DdnsClient {
         Ok(record)
     }
 
-    /// Update an existing DNS record with a new IP
+    /// Update an existing DNS record with new content
     pub async fn update_record(
         &self,
         zone_id: &str,
         record: &DnsRecord,
-        new_ip: IpAddr,
-        proxied: bool,
-        ttl: u32,
+        record_config: &RecordConfig,
+        content: &str,
     ) -> Result<()> {
-        let content = match new_ip {
-            IpAddr::V4(ip) => DnsContent::A { content: ip },
-            IpAddr::V6(ip) => DnsContent::AAAA { content: ip },
-        };
-
         let endpoint = UpdateDnsRecord {
             zone_identifier: zone_id,
             identifier: &record.id,
-            params: UpdateDnsRecordParams {
-                name: &record.name,
+            params: DnsRecordParams::new(
+                record_config,
                 content,
-                ttl: Some(ttl),
-                proxied: Some(proxied),
-            },
+                self.comment_for(record_config, Some(record)),
+                self.tags_for(record_config, Some(record)),
+            )?,
         };
 
         self.client
@@ -138,7 +262,7 @@ impl DdnsClient {
             .await
             .context("Failed to update DNS record")?;
 
-        info!("Updated {} -> {}", record.name, new_ip);
+        info!("Updated {} -> {}", record.name, content);
         Ok(())
     }
 
@@ -146,25 +270,17 @@ impl DdnsClient {
     pub async fn create_record(
         &self,
         zone_id: &str,
-        record_name: &str,
-        ip: IpAddr,
-        proxied: bool,
-        ttl: u32,
+        record_config: &RecordConfig,
+        content: &str,
     ) -> Result<()> {
-        let content = match ip {
-            IpAddr::V4(ip) => DnsContent::A { content: ip },
-            IpAddr::V6(ip) => DnsContent::AAAA { content: ip },
-        };
-
         let endpoint = CreateDnsRecord {
             zone_identifier: zone_id,
-            params: CreateDnsRecordParams {
-                name: record_name,
+            params: DnsRecordParams::new(
+                record_config,
                 content,
-                ttl: Some(ttl),
-                proxied: Some(proxied),
-                priority: None,
-            },
+                self.comment_for(record_config, None),
+                self.tags_for(record_config, None),
+            )?,
         };
 
         self.client
@@ -172,17 +288,19 @@ impl DdnsClient {
             .await
             .context("Failed to create DNS record")?;
 
-        info!("Created {} -> {}", record_name, ip);
+        info!("Created {} -> {}", record_config.name, content);
         Ok(())
     }
 
     /// Update a DNS record configuration with the given IP
-    /// Creates the record if it doesn't exist, updates it if the IP has changed
+    /// Creates the record if it doesn't exist, updates it if the content has changed
     pub async fn update_ddns(
         &self,
         record_config: &RecordConfig,
         ip: IpAddr,
     ) -> Result<UpdateResult> {
+        let content = record_config.content_for(ip)?;
+
         // Get the zone ID
         let zone_id = self.get_zone_id(&record_config.zone).await?;
 
@@ -193,26 +311,23 @@ impl DdnsClient {
 
         match existing {
             Some(record) => {
-                // Check if IP has changed
-                let current_ip = extract_ip(&record.content);
-
-                if current_ip == Some(ip) {
-                    debug!("{} already points to {}, skipping", record_config.name, ip);
+                // Check if the content, comment or tags have changed
+                if same_content(record_config.record_type, &record.content, &content)
+                    && self.same_metadata(&record, record_config)
+                {
+                    debug!(
+                        "{} already points to {}, skipping",
+                        record_config.name, content
+                    );
                     return Ok(UpdateResult::Unchanged);
                 }
 
                 // Update the record
-                self.update_record(
-                    &zone_id,
-                    &record,
-                    ip,
-                    record_config.proxied,
-                    record_config.ttl,
-                )
-                .await?;
+                self.update_record(&zone_id, &record, record_config, &content)
+                    .await?;
 
                 Ok(UpdateResult::Updated {
-                    old_ip: current_ip,
+                    old_ip: extract_ip(&record),
                     new_ip: ip,
                 })
             }
@@ -223,40 +338,414 @@ impl DdnsClient {
                     record_config.name, record_config.record_type
                 );
 
-                self.create_record(
-                    &zone_id,
-                    &record_config.name,
-                    ip,
-                    record_config.proxied,
-                    record_config.ttl,
-                )
-                .await?;
+                self.create_record(&zone_id, record_config, &content)
+                    .await?;
 
                 Ok(UpdateResult::Created)
             }
         }
     }
+
+    /// Comment to write on a record: the configured comment plus the marker
+    ///
+    /// Without a configured comment the existing one is kept, so comments
+    /// written in the dashboard survive updates.
+    fn comment_for(
+        &self,
+        record_config: &RecordConfig,
+        existing: Option<&DnsRecord>,
+    ) -> Option<String> {
+        if record_config.comment.is_none() && !self.mark_managed {
+            return existing.and_then(|record| record.comment.clone());
+        }
+
+        let mut parts = Vec::new();
+        if let Some(comment) = record_config
+            .comment
+            .clone()
+            .or_else(|| existing.and_then(user_comment))
+        {
+            parts.push(comment);
+        }
+        if self.mark_managed {
+            parts.push(format!("{}/{}", MANAGED_MARKER, self.owner_id));
+            parts.push(format!(
+                "{}{}",
+                UPDATED_PREFIX,
+                Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+            ));
+        }
+
+        (!parts.is_empty()).then(|| parts.join(COMMENT_SEPARATOR))
+    }
+
+    /// Tags to write on a record: the configured ones, or else the existing ones
+    fn tags_for(&self, record_config: &RecordConfig, existing: Option<&DnsRecord>) -> Vec<String> {
+        match existing {
+            Some(record) if record_config.tags.is_empty() => record.tags.clone(),
+            _ => record_config.tags.clone(),
+        }
+    }
+
+    /// Whether the record's comment and tags already match the configuration
+    fn same_metadata(&self, record: &DnsRecord, record_config: &RecordConfig) -> bool {
+        let desired = self.comment_for(record_config, Some(record));
+        let same_comment = strip_timestamp(record.comment.as_deref().unwrap_or_default())
+            == strip_timestamp(desired.as_deref().unwrap_or_default());
+
+        let mut current_tags = record.tags.clone();
+        let mut desired_tags = self.tags_for(record_config, Some(record));
+        current_tags.sort();
+        desired_tags.sort();
+
+        same_comment && current_tags == desired_tags
+    }
+}
+
+/// Create a Cloudflare API client authenticated with the given token
+pub fn api_client(api_token: &str) -> Result<ApiClient> {
+    let credentials = Credentials::UserAuthToken {
+        token: api_token.to_string(),
+    };
+
+    let client = Client::new(
+        credentials,
+        ClientConfig::default(),
+        Environment::Production,
+    )
+    .context("Failed to create Cloudflare client")?;
+    Ok(ApiClient { client })
 }
 
-/// Result of a DDNS update operation
-#[derive(Debug)]
-pub enum UpdateResult {
-    /// Record was created (didn't exist before)
-    Created,
-    /// Record was updated with new IP
-    Updated {
-        old_ip: Option<IpAddr>,
-        new_ip: IpAddr,
-    },
-    /// Record already had the correct IP
-    Unchanged,
+/// Cloudflare API client that backs off while the API is rate limiting
+pub struct ApiClient {
+    client: Client,
 }
 
-/// Extract IP address from DNS content
-fn extract_ip(content: &DnsContent) -> Option<IpAddr> {
-    match content {
-        DnsContent::A { content } => Some(IpAddr::V4(*content)),
-        DnsContent::AAAA { content } => Some(IpAddr::V6(*content)),
+impl ApiClient {
+    /// Issue a request, retrying after a growing pause when it is rate limited
+    ///
+    /// The underlying client doesn't expose response headers, so Retry-After
+    /// can't be honoured; the pauses double from [`RATE_LIMIT_BACKOFF`] instead.
+    pub async fn request<Endpoint>(
+        &self,
+        endpoint: &Endpoint,
+    ) -> ApiResponse<Endpoint::ResponseType>
+    where
+        Endpoint: EndpointSpec + Send + Sync,
+        Endpoint::ResponseType: ResponseConverter<Endpoint::JsonResponse>,
+    {
+        let mut delay = RATE_LIMIT_BACKOFF;
+        for _ in 0..RATE_LIMIT_RETRIES {
+            match self.client.request(endpoint).await {
+                Err(ApiFailure::Error(status, _)) if status == StatusCode::TOO_MANY_REQUESTS => {
+                    warn!(
+                        "Cloudflare API rate limit reached; retrying in {}",
+                        humantime::format_duration(delay)
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                response => return response,
+            }
+        }
+        self.client.request(endpoint).await
+    }
+}
+
+/// Whether a record comment carries the ownership marker of the given instance
+///
+/// Records marked without an owner (by older versions) belong to no instance.
+pub fn is_owned_by(record: &DnsRecord, owner_id: &str) -> bool {
+    record.comment.as_deref().is_some_and(|c| {
+        c.split(COMMENT_SEPARATOR).any(|part| {
+            part.strip_prefix(MANAGED_MARKER)
+                .and_then(|rest| rest.strip_prefix('/'))
+                == Some(owner_id)
+        })
+    })
+}
+
+/// Record comment without the parts cddns adds itself
+pub fn user_comment(record: &DnsRecord) -> Option<String> {
+    let comment = record
+        .comment
+        .as_deref()?
+        .split(COMMENT_SEPARATOR)
+        .filter(|part| !part.starts_with(MANAGED_MARKER) && !part.starts_with(UPDATED_PREFIX))
+        .collect::<Vec<_>>()
+        .join(COMMENT_SEPARATOR);
+    (!comment.is_empty()).then_some(comment)
+}
+
+/// Read the page count from a list response
+fn total_pages(result_info: &Option<serde_json::Value>) -> u32 {
+    result_info
+        .as_ref()
+        .and_then(|info| info.get("total_pages"))
+        .and_then(|pages| pages.as_u64())
+        .unwrap_or(1) as u32
+}
+
+/// Remove the last-update timestamp from a record comment
+fn strip_timestamp(comment: &str) -> String {
+    comment
+        .split(COMMENT_SEPARATOR)
+        .filter(|part| !part.starts_with(UPDATED_PREFIX))
+        .collect::<Vec<_>>()
+        .join(COMMENT_SEPARATOR)
+}
+
+/// DNS record of any type, as returned by the Cloudflare API
+#[derive(Deserialize, Debug)]
+pub struct DnsRecord {
+    /// DNS record identifier tag
+    pub id: String,
+    /// DNS record name
+    pub name: String,
+    /// Record type (e.g., "A", "TXT", "HTTPS")
+    #[serde(rename = "type")]
+    pub record_type: String,
+    /// Record content in presentation format
+    #[serde(default)]
+    pub content: String,
+    /// Whether the record is proxied through Cloudflare
+    #[serde(default)]
+    pub proxied: bool,
+    /// Time to live in seconds (1 = automatic, also when the API leaves it out)
+    #[serde(default = "crate::config::default_ttl")]
+    pub ttl: u32,
+    /// Record comment
+    #[serde(default)]
+    pub comment: Option<String>,
+    /// Record tags in "name:value" form
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Records returned by list requests
+#[derive(Deserialize, Debug)]
+#[serde(transparent)]
+pub struct DnsRecords(pub Vec<DnsRecord>);
+
+impl ApiResult for DnsRecord {}
+impl ApiResult for DnsRecords {}
+
+/// Body of create/update requests
+#[derive(Serialize, Debug)]
+struct DnsRecordParams<'a> {
+    name: &'a str,
+    #[serde(rename = "type")]
+    record_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<SvcbData>,
+    ttl: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    proxied: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    tags: Vec<String>,
+}
+
+/// Structured content of HTTPS and SVCB records
+#[derive(Serialize, Debug)]
+struct SvcbData {
+    priority: u16,
+    target: String,
+    value: String,
+}
+
+impl<'a> DnsRecordParams<'a> {
+    fn new(
+        record_config: &'a RecordConfig,
+        content: &str,
+        comment: Option<String>,
+        tags: Vec<String>,
+    ) -> Result<Self> {
+        let record_type = record_config.record_type;
+
+        let (content, data) = match record_type {
+            RecordType::HTTPS | RecordType::SVCB => (None, Some(parse_svcb(content)?)),
+            _ => (Some(content.to_string()), None),
+        };
+
+        Ok(Self {
+            name: &record_config.name,
+            record_type: record_type.to_string(),
+            content,
+            data,
+            ttl: record_config.ttl,
+            proxied: record_type.is_proxiable().then_some(record_config.proxied),
+            comment,
+            tags,
+        })
+    }
+}
+
+/// Split "priority target params..." into HTTPS/SVCB data
+fn parse_svcb(content: &str) -> Result<SvcbData> {
+    let mut parts = content.split_whitespace();
+    let priority = parts
+        .next()
+        .and_then(|p| p.parse().ok())
+        .with_context(|| format!("Invalid SVCB priority in content: {}", content))?;
+    let target = parts
+        .next()
+        .with_context(|| format!("Missing SVCB target in content: {}", content))?
+        .to_string();
+
+    Ok(SvcbData {
+        priority,
+        target,
+        value: parts.collect::<Vec<_>>().join(" "),
+    })
+}
+
+/// List DNS records filtered by name and type
+struct ListDnsRecords<'a> {
+    zone_identifier: &'a str,
+    params: ListDnsRecordsParams,
+}
+
+#[derive(Serialize, Debug, Default)]
+struct ListDnsRecordsParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    record_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    per_page: Option<u32>,
+}
+
+impl EndpointSpec for ListDnsRecords<'_> {
+    type JsonResponse = DnsRecords;
+    type ResponseType = ApiSuccess<Self::JsonResponse>;
+
+    fn method(&self) -> Method {
+        Method::GET
+    }
+    fn path(&self) -> String {
+        format!("zones/{}/dns_records", self.zone_identifier)
+    }
+    fn query(&self) -> Option<String> {
+        serialize_query(&self.params)
+    }
+}
+
+/// Create a DNS record
+struct CreateDnsRecord<'a> {
+    zone_identifier: &'a str,
+    params: DnsRecordParams<'a>,
+}
+
+impl EndpointSpec for CreateDnsRecord<'_> {
+    type JsonResponse = DnsRecord;
+    type ResponseType = ApiSuccess<Self::JsonResponse>;
+
+    fn method(&self) -> Method {
+        Method::POST
+    }
+    fn path(&self) -> String {
+        format!("zones/{}/dns_records", self.zone_identifier)
+    }
+    fn body(&self) -> Option<RequestBody<'_>> {
+        serde_json::to_string(&self.params)
+            .ok()
+            .map(RequestBody::Json)
+    }
+}
+
+/// Overwrite a DNS record
+struct UpdateDnsRecord<'a> {
+    zone_identifier: &'a str,
+    identifier: &'a str,
+    params: DnsRecordParams<'a>,
+}
+
+impl EndpointSpec for UpdateDnsRecord<'_> {
+    type JsonResponse = DnsRecord;
+    type ResponseType = ApiSuccess<Self::JsonResponse>;
+
+    fn method(&self) -> Method {
+        Method::PUT
+    }
+    fn path(&self) -> String {
+        format!(
+            "zones/{}/dns_records/{}",
+            self.zone_identifier, self.identifier
+        )
+    }
+    fn body(&self) -> Option<RequestBody<'_>> {
+        serde_json::to_string(&self.params)
+            .ok()
+            .map(RequestBody::Json)
+    }
+}
+
+/// Status of the token used for the request
+#[derive(Deserialize, Debug)]
+struct TokenStatus {
+    status: String,
+}
+
+impl ApiResult for TokenStatus {}
+
+/// Verify the API token
+struct VerifyToken;
+
+impl EndpointSpec for VerifyToken {
+    type JsonResponse = TokenStatus;
+    type ResponseType = ApiSuccess<Self::JsonResponse>;
+
+    fn method(&self) -> Method {
+        Method::GET
+    }
+    fn path(&self) -> String {
+        "user/tokens/verify".to_string()
+    }
+}
+
+/// Extract IP address from an address record
+fn extract_ip(record: &DnsRecord) -> Option<IpAddr> {
+    match record.record_type.as_str() {
+        "A" | "AAAA" => record.content.parse().ok(),
         _ => None,
     }
 }
+
+/// Compare existing and desired content, ignoring presentation differences
+fn same_content(record_type: RecordType, current: &str, desired: &str) -> bool {
+    match record_type {
+        RecordType::A | RecordType::AAAA => {
+            current.parse::<IpAddr>().ok() == desired.parse::<IpAddr>().ok()
+        }
+        RecordType::CNAME => current
+            .trim_end_matches('.')
+            .eq_ignore_ascii_case(desired.trim_end_matches('.')),
+        RecordType::TXT => unquote(current) == unquote(desired),
+        RecordType::HTTPS | RecordType::SVCB => normalize_svcb(current) == normalize_svcb(desired),
+    }
+}
+
+/// Strip the quotes Cloudflare may add around TXT content
+fn unquote(content: &str) -> &str {
+    let content = content.trim();
+    content
+        .strip_prefix('"')
+        .and_then(|c| c.strip_suffix('"'))
+        .unwrap_or(content)
+}
+
+/// Normalize whitespace and quoting of HTTPS/SVCB presentation format
+fn normalize_svcb(content: &str) -> String {
+    content
+        .split_whitespace()
+        .map(|part| part.replace('"', ""))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -1,39 +1,174 @@
 use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::net::IpAddr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::rules::{self, Severity};
+
+/// Current config layout version
+pub const CONFIG_VERSION: u32 = 2;
 
 /// Main configuration structure
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
+    /// Config layout version (older layouts are migrated on load)
+    #[serde(default = "default_version")]
+    pub version: u32,
     /// Cloudflare API configuration
+    #[serde(default)]
     pub cloudflare: CloudflareConfig,
+    /// RFC 2136 dynamic update server (for BIND, Knot, etc.)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rfc2136: Option<Rfc2136Config>,
+    /// Named webhooks for arbitrary DNS APIs
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub webhooks: BTreeMap<String, WebhookConfig>,
     /// DNS records to update
+    #[serde(default)]
     pub records: Vec<RecordConfig>,
+    /// Cloudflare IP Lists holding an entry for the dynamic IP
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ip_lists: Vec<IpListConfig>,
+    /// Cloudflare Load Balancer pool origins pointing at the dynamic IP
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub origins: Vec<OriginConfig>,
     /// Optional settings
     #[serde(default)]
     pub settings: Settings,
     /// Service mode settings
     #[serde(default)]
     pub service: ServiceConfig,
+    /// Values resolved from `${VAR}` references or elsewhere, written back as they were
+    #[serde(skip)]
+    pub references: Vec<Reference>,
+}
+
+/// A string value as written in the file, and what it resolved to
+///
+/// Saving writes the reference back instead of the secret it expanded to,
+/// unless the value was changed in the meantime.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    path: Vec<KeyPart>,
+    written: String,
+    resolved: String,
+}
+
+/// A step on the way to a value: a table key or an array index
+#[derive(Debug, Clone, PartialEq)]
+enum KeyPart {
+    Key(String),
+    Index(usize),
 }
 
 /// Cloudflare authentication configuration
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Default, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct CloudflareConfig {
     /// API token (recommended) - requires Zone:Read and DNS:Edit permissions
+    ///
+    /// Falls back to the `CF_API_TOKEN` environment variable when empty.
+    #[serde(default)]
     pub api_token: String,
+    /// Read the API token from a file (Docker/Kubernetes secrets, systemd credentials)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_token_file: Option<PathBuf>,
+}
+
+impl CloudflareConfig {
+    /// Resolve the token from `api_token_file` or `CF_API_TOKEN` if needed
+    fn resolve_token(&mut self) -> Result<()> {
+        if let Some(file) = &self.api_token_file {
+            let token = fs::read_to_string(file).with_context(|| {
+                format!(
+                    "Failed to read Cloudflare API token file: {}",
+                    file.display()
+                )
+            })?;
+            self.api_token = token.trim().to_string();
+        } else if self.api_token.is_empty() {
+            if let Ok(token) = std::env::var("CF_API_TOKEN") {
+                self.api_token = token.trim().to_string();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// RFC 2136 dynamic update configuration
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Rfc2136Config {
+    /// Primary server accepting updates (e.g., "10.0.0.53" or "ns1.internal:53")
+    pub server: String,
+    /// TSIG key name (e.g., "cddns-key")
+    pub key_name: String,
+    /// Base64-encoded TSIG key secret
+    pub key_secret: String,
+    /// TSIG algorithm (only "hmac-sha256" is supported)
+    #[serde(default)]
+    pub algorithm: TsigAlgorithm,
+}
+
+/// Webhook request issued when a record needs changing
+///
+/// `url`, header values and `body` may contain the `{ip}`, `{name}`, `{zone}`
+/// and `{type}` placeholders.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    /// HTTP method (e.g., "GET", "POST", "PUT")
+    #[serde(default = "default_webhook_method")]
+    pub method: String,
+    /// URL template (e.g., "https://www.duckdns.org/update?domains={name}&ip={ip}")
+    pub url: String,
+    /// Extra request headers
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Optional request body template
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// Optional: Text the response body must contain for the update to count as successful
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expect_body: Option<String>,
+}
+
+fn default_version() -> u32 {
+    1 // Files written before versioning
+}
+
+fn default_webhook_method() -> String {
+    "GET".to_string()
+}
+
+fn default_list_comment() -> String {
+    "cddns".to_string()
+}
+
+/// Supported TSIG algorithms
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TsigAlgorithm {
+    #[default]
+    #[serde(rename = "hmac-sha256")]
+    HmacSha256,
 }
 
 /// DNS record configuration
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct RecordConfig {
     /// The zone name (e.g., "example.com")
     pub zone: String,
     /// The full record name (e.g., "home.example.com")
     pub name: String,
-    /// Record type: "A" for IPv4, "AAAA" for IPv6
+    /// Record type: "A", "AAAA", "TXT", "CNAME", "HTTPS" or "SVCB"
     #[serde(default = "default_record_type")]
     pub record_type: RecordType,
     /// Whether the record should be proxied through Cloudflare
@@ -42,10 +177,156 @@ pub struct RecordConfig {
     /// TTL in seconds (1 = automatic)
     #[serde(default = "default_ttl")]
     pub ttl: u32,
+    /// Provider managing the record: "cloudflare" (default), "rfc2136" or "webhook"
+    #[serde(default, skip_serializing_if = "Provider::is_cloudflare")]
+    pub provider: Provider,
+    /// Name of the webhook to call (for provider = "webhook")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<String>,
+    /// Content template for TXT, CNAME, HTTPS and SVCB records (e.g., "v=spf1 ip4:{ip} -all")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// Content overrides picked by the network the detected IP belongs to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub content_rules: Vec<ContentRule>,
+    /// Address family to detect for non-address records: "ipv4" (default) or "ipv6"
+    #[serde(default, skip_serializing_if = "IpVersion::is_v4")]
+    pub ip_version: IpVersion,
+    /// Optional: Cloudflare record comment
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    /// Optional: Cloudflare record tags in "name:value" form
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Optional: cron expression for this record instead of the [service] schedule
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>,
+    /// Optional: update interval for this record instead of the [service] schedule (e.g., "24h")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval: Option<String>,
+}
+
+/// Entry in a Cloudflare account-level IP List kept at the dynamic IP
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct IpListConfig {
+    /// Cloudflare account ID owning the list
+    pub account_id: String,
+    /// List name (e.g., "home_ips")
+    pub list: String,
+    /// Comment identifying our entry in the list
+    #[serde(default = "default_list_comment")]
+    pub comment: String,
+    /// Address family to keep in the list (IPv6 entries are stored as their /64)
+    #[serde(default, skip_serializing_if = "IpVersion::is_v4")]
+    pub ip_version: IpVersion,
+}
+
+/// Origin in a Cloudflare Load Balancer pool kept at the dynamic IP
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct OriginConfig {
+    /// Cloudflare account ID owning the pool
+    pub account_id: String,
+    /// Pool name or ID (e.g., "homelab")
+    pub pool: String,
+    /// Origin name within the pool (e.g., "home")
+    pub origin: String,
+    /// Address family of the origin address
+    #[serde(default, skip_serializing_if = "IpVersion::is_v4")]
+    pub ip_version: IpVersion,
+}
+
+/// Non-DNS target that follows the dynamic IP
+#[derive(Debug, Clone, Copy)]
+pub enum Target<'a> {
+    IpList(&'a IpListConfig),
+    Origin(&'a OriginConfig),
+}
+
+impl Target<'_> {
+    /// Record type whose address family should be detected for this target
+    pub fn ip_record_type(&self) -> RecordType {
+        match self {
+            Target::IpList(list) => list.ip_version.record_type(),
+            Target::Origin(origin) => origin.ip_version.record_type(),
+        }
+    }
+}
+
+impl std::fmt::Display for Target<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::IpList(list) => write!(f, "IP list {}", list.list),
+            Target::Origin(origin) => {
+                write!(f, "origin {} (pool {})", origin.origin, origin.pool)
+            }
+        }
+    }
+}
+
+/// Content used when the detected IP falls inside a network
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ContentRule {
+    /// Network in CIDR notation (e.g., "198.51.100.0/24")
+    pub network: String,
+    /// Content template used for this network (e.g., "office.example.com")
+    pub content: String,
+}
+
+/// IP address family
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum IpVersion {
+    #[default]
+    Ipv4,
+    Ipv6,
+}
+
+impl IpVersion {
+    fn is_v4(&self) -> bool {
+        *self == IpVersion::Ipv4
+    }
+
+    /// Address record type used to detect an IP of this family
+    pub fn record_type(&self) -> RecordType {
+        match self {
+            IpVersion::Ipv4 => RecordType::A,
+            IpVersion::Ipv6 => RecordType::AAAA,
+        }
+    }
+}
+
+/// DNS providers that can hold a record
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    #[default]
+    Cloudflare,
+    Rfc2136,
+    Webhook,
+}
+
+impl Provider {
+    pub fn is_cloudflare(&self) -> bool {
+        *self == Provider::Cloudflare
+    }
+}
+
+impl std::fmt::Display for Provider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Provider::Cloudflare => write!(f, "cloudflare"),
+            Provider::Rfc2136 => write!(f, "rfc2136"),
+            Provider::Webhook => write!(f, "webhook"),
+        }
+    }
 }
 
 /// Optional settings
-#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Settings {
     /// URL to fetch public IPv4 address
     #[serde(default = "default_ipv4_url")]
@@ -56,30 +337,203 @@ pub struct Settings {
     /// Optional: Force a specific IP instead of auto-detecting
     #[serde(skip_serializing_if = "Option::is_none")]
     pub force_ip: Option<IpAddr>,
+    /// Stamp Cloudflare records with a "managed-by: cddns/<owner_id>" marker and the last update time
+    #[serde(default)]
+    pub mark_managed: bool,
+    /// Identifies this instance in the marker; only records carrying it are pruned
+    #[serde(default = "default_owner_id")]
+    pub owner_id: String,
+    /// Delete managed records that are no longer configured after each update cycle
+    #[serde(default)]
+    pub prune_orphans: bool,
+    /// Zones to search for orphans besides those of the configured records
+    /// (e.g., a zone whose records were all removed)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub prune_zones: Vec<String>,
+    /// How many zones the service updates at once (records of one zone go in order)
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            ipv4_url: default_ipv4_url(),
+            ipv6_url: default_ipv6_url(),
+            force_ip: None,
+            mark_managed: false,
+            owner_id: default_owner_id(),
+            prune_orphans: false,
+            prune_zones: Vec::new(),
+            concurrency: default_concurrency(),
+        }
+    }
 }
 
 /// Service mode configuration
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct ServiceConfig {
-    /// Cron expression for scheduling updates (e.g., "*/5 * * * *" for every 5 minutes)
+    /// Cron expression for scheduling updates (e.g., "0 */5 * * * *" for every 5 minutes)
     #[serde(default = "default_cron")]
     pub cron: String,
+    /// IANA time zone the cron expression is evaluated in (e.g., "Europe/Berlin"; default: UTC)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    /// Run updates at a fixed interval instead of the cron schedule (e.g., "5m", "1h 30m")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval: Option<String>,
+    /// Delay each scheduled update by a random amount up to this duration (e.g., "30s")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jitter: Option<String>,
+    /// Skip scheduled updates that come sooner than this after the previous update
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_spacing: Option<String>,
     /// Whether to run an update immediately on service start
     #[serde(default = "default_run_on_start")]
     pub run_on_start: bool,
+    /// Update right away when a global address or the default route changes (Linux only)
+    #[serde(default)]
+    pub watch_network: bool,
+    /// Optional dyndns2 listener so routers can push their IP
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dyndns: Option<DyndnsConfig>,
 }
 
 impl Default for ServiceConfig {
     fn default() -> Self {
         Self {
             cron: default_cron(),
+            timezone: None,
+            interval: None,
+            jitter: None,
+            min_spacing: None,
             run_on_start: default_run_on_start(),
+            watch_network: false,
+            dyndns: None,
         }
     }
 }
 
+impl ServiceConfig {
+    /// Time zone of the cron schedule
+    pub fn timezone(&self) -> Result<chrono_tz::Tz> {
+        match &self.timezone {
+            Some(name) => parse_timezone(name),
+            None => Ok(chrono_tz::Tz::UTC),
+        }
+    }
+
+    /// When scheduled updates run: the interval if set, the cron expression otherwise
+    pub fn schedule(&self) -> Result<Schedule> {
+        match &self.interval {
+            Some(interval) => {
+                let every = parse_duration(interval)?;
+                if every.is_zero() {
+                    anyhow::bail!("interval must be longer than zero");
+                }
+                Ok(Schedule::Interval(every))
+            }
+            None => {
+                parse_cron(&self.cron)?;
+                Ok(Schedule::Cron {
+                    expr: self.cron.clone(),
+                    timezone: self.timezone()?,
+                })
+            }
+        }
+    }
+
+    /// Upper bound of the random delay before scheduled updates
+    pub fn jitter(&self) -> Result<Option<Duration>> {
+        self.jitter.as_deref().map(parse_duration).transpose()
+    }
+
+    /// Minimum time between the previous update and a scheduled one
+    pub fn min_spacing(&self) -> Result<Option<Duration>> {
+        self.min_spacing.as_deref().map(parse_duration).transpose()
+    }
+}
+
+/// When scheduled updates run
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Schedule {
+    /// Six-field cron expression evaluated in a time zone
+    Cron {
+        expr: String,
+        timezone: chrono_tz::Tz,
+    },
+    /// Fixed interval, counted from when the schedule starts
+    Interval(Duration),
+}
+
+impl Schedule {
+    /// The next `count` runs after `after`
+    ///
+    /// For intervals `after` is taken to be a run, so the first result is one
+    /// interval later.
+    pub fn next_runs(&self, after: DateTime<Utc>, count: usize) -> Vec<DateTime<Utc>> {
+        match self {
+            Schedule::Cron { expr, timezone } => match parse_cron(expr) {
+                Ok(cron) => cron
+                    .iter_after(after.with_timezone(timezone))
+                    .take(count)
+                    .map(|time| time.with_timezone(&Utc))
+                    .collect(),
+                Err(_) => Vec::new(),
+            },
+            Schedule::Interval(every) => {
+                let Ok(every) = chrono::Duration::from_std(*every) else {
+                    return Vec::new();
+                };
+                (1..=count as i32).map(|n| after + every * n).collect()
+            }
+        }
+    }
+
+    /// Time between the next two runs
+    pub fn period(&self) -> Option<Duration> {
+        match self {
+            Schedule::Interval(every) => Some(*every),
+            Schedule::Cron { .. } => match self.next_runs(Utc::now(), 2)[..] {
+                [first, second] => (second - first).to_std().ok(),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for Schedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Schedule::Cron { expr, timezone } if *timezone == chrono_tz::Tz::UTC => {
+                write!(f, "{}", expr)
+            }
+            Schedule::Cron { expr, timezone } => write!(f, "{} ({})", expr, timezone),
+            Schedule::Interval(every) => write!(f, "every {}", humantime::format_duration(*every)),
+        }
+    }
+}
+
+/// dyndns2 protocol listener configuration
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct DyndnsConfig {
+    /// Address to listen on (e.g., "0.0.0.0:8245")
+    #[serde(default = "default_dyndns_listen")]
+    pub listen: String,
+    /// Username routers authenticate with
+    pub username: String,
+    /// Password routers authenticate with
+    pub password: String,
+}
+
+fn default_dyndns_listen() -> String {
+    "0.0.0.0:8245".to_string()
+}
+
 fn default_cron() -> String {
-    "*/5 * * * *".to_string() // Every 5 minutes
+    "0 */5 * * * *".to_string() // Every 5 minutes
 }
 
 fn default_run_on_start() -> bool {
@@ -88,11 +542,27 @@ fn default_run_on_start() -> bool {
 
 /// Supported DNS record types for DDNS
 #[allow(clippy::upper_case_acronyms)]
-#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, Default)]
 pub enum RecordType {
     #[default]
     A,
     AAAA,
+    TXT,
+    CNAME,
+    HTTPS,
+    SVCB,
+}
+
+impl RecordType {
+    /// Whether the record content is the IP address itself
+    pub fn is_address(&self) -> bool {
+        matches!(self, RecordType::A | RecordType::AAAA)
+    }
+
+    /// Whether Cloudflare can proxy records of this type
+    pub fn is_proxiable(&self) -> bool {
+        matches!(self, RecordType::A | RecordType::AAAA | RecordType::CNAME)
+    }
 }
 
 impl std::fmt::Display for RecordType {
@@ -100,6 +570,10 @@ impl std::fmt::Display for RecordType {
         match self {
             RecordType::A => write!(f, "A"),
             RecordType::AAAA => write!(f, "AAAA"),
+            RecordType::TXT => write!(f, "TXT"),
+            RecordType::CNAME => write!(f, "CNAME"),
+            RecordType::HTTPS => write!(f, "HTTPS"),
+            RecordType::SVCB => write!(f, "SVCB"),
         }
     }
 }
@@ -111,19 +585,232 @@ impl std::str::FromStr for RecordType {
         match s.to_uppercase().as_str() {
             "A" => Ok(RecordType::A),
             "AAAA" => Ok(RecordType::AAAA),
-            _ => anyhow::bail!("Invalid record type: {}. Use 'A' or 'AAAA'", s),
+            "TXT" => Ok(RecordType::TXT),
+            "CNAME" => Ok(RecordType::CNAME),
+            "HTTPS" => Ok(RecordType::HTTPS),
+            "SVCB" => Ok(RecordType::SVCB),
+            _ => anyhow::bail!(
+                "Invalid record type: {}. Use 'A', 'AAAA', 'TXT', 'CNAME', 'HTTPS' or 'SVCB'",
+                s
+            ),
+        }
+    }
+}
+
+impl RecordConfig {
+    /// Whether the record name is the zone apex or below it
+    pub fn in_zone(&self) -> bool {
+        name_in_zone(&self.name, &self.zone)
+    }
+
+    /// When this record is updated: its own interval or cron, else the service schedule
+    ///
+    /// Record cron expressions use the service time zone.
+    pub fn schedule(&self, service: &ServiceConfig) -> Result<Schedule> {
+        match (&self.interval, &self.cron) {
+            (None, None) => service.schedule(),
+            (interval, cron) => ServiceConfig {
+                cron: cron.clone().unwrap_or_default(),
+                interval: interval.clone(),
+                ..service.clone()
+            }
+            .schedule(),
+        }
+    }
+
+    /// Identity of the record for duplicate checks
+    pub fn key(&self) -> RecordKey {
+        RecordKey::new(
+            Some(&self.provider.to_string()),
+            &self.zone,
+            self.webhook.as_deref(),
+            &self.name,
+            Some(&self.record_type.to_string()),
+        )
+    }
+
+    /// Record type whose address family should be detected for this record
+    pub fn ip_record_type(&self) -> RecordType {
+        match (self.record_type, self.ip_version) {
+            (RecordType::A | RecordType::AAAA, _) => self.record_type,
+            (_, ip_version) => ip_version.record_type(),
+        }
+    }
+
+    /// Desired record content for the given IP
+    ///
+    /// Address records hold the IP itself; other types render the first
+    /// matching content rule, falling back to `content`.
+    pub fn content_for(&self, ip: IpAddr) -> Result<String> {
+        if self.record_type.is_address() {
+            return Ok(ip.to_string());
+        }
+
+        for rule in &self.content_rules {
+            if network_contains(&rule.network, ip)? {
+                return Ok(self.render(&rule.content, ip));
+            }
         }
+
+        let template = self.content.as_deref().with_context(|| {
+            format!(
+                "No content configured for {} record {}",
+                self.record_type, self.name
+            )
+        })?;
+        Ok(self.render(template, ip))
+    }
+
+    /// Fill the `{ip}`, `{name}`, `{zone}` and `{type}` placeholders of a template
+    pub fn render(&self, template: &str, ip: IpAddr) -> String {
+        template
+            .replace("{ip}", &ip.to_string())
+            .replace("{name}", &self.name)
+            .replace("{zone}", &self.zone)
+            .replace("{type}", &self.record_type.to_string())
     }
 }
 
+/// Check whether a CIDR network (or a single address) contains an IP
+pub fn network_contains(network: &str, ip: IpAddr) -> Result<bool> {
+    let (addr, prefix) = match network.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (network, None),
+    };
+    let addr: IpAddr = addr
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid network address: {}", network))?;
+    let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix: u32 = match prefix {
+        Some(prefix) => prefix
+            .trim()
+            .parse()
+            .ok()
+            .filter(|p| *p <= max_prefix)
+            .with_context(|| format!("Invalid network prefix: {}", network))?,
+        None => max_prefix,
+    };
+
+    let (net, ip) = match (addr, ip) {
+        (IpAddr::V4(net), IpAddr::V4(ip)) => (u32::from(net) as u128, u32::from(ip) as u128),
+        (IpAddr::V6(net), IpAddr::V6(ip)) => (u128::from(net), u128::from(ip)),
+        _ => return Ok(false),
+    };
+    let shift = max_prefix - prefix;
+    Ok(shift == max_prefix || (net >> shift) == (ip >> shift))
+}
+
 fn default_record_type() -> RecordType {
     RecordType::A
 }
 
-fn default_ttl() -> u32 {
+pub fn default_ttl() -> u32 {
     1 // Automatic TTL
 }
 
+fn default_owner_id() -> String {
+    "default".to_string()
+}
+
+fn default_concurrency() -> usize {
+    4
+}
+
+/// What makes two records the same DNS entry
+///
+/// The provider and where it writes are part of the key, so a split-horizon
+/// setup can keep one name on Cloudflare and on an internal server.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RecordKey {
+    provider: String,
+    /// Zone for Cloudflare and RFC 2136 (one server), webhook name for webhooks
+    target: String,
+    name: String,
+    record_type: String,
+}
+
+impl RecordKey {
+    /// Key of a record as written; missing provider and type take their defaults
+    pub fn new(
+        provider: Option<&str>,
+        zone: &str,
+        webhook: Option<&str>,
+        name: &str,
+        record_type: Option<&str>,
+    ) -> Self {
+        let provider = provider.unwrap_or("cloudflare").to_lowercase();
+        let target = match provider.as_str() {
+            "webhook" => webhook.unwrap_or_default().to_string(),
+            _ => zone.trim_end_matches('.').to_lowercase(),
+        };
+        Self {
+            provider,
+            target,
+            name: name.trim_end_matches('.').to_lowercase(),
+            record_type: record_type.unwrap_or("A").to_uppercase(),
+        }
+    }
+}
+
+impl std::fmt::Display for RecordKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.provider.as_str() {
+            "webhook" => write!(
+                f,
+                "{} record {} (webhook {})",
+                self.record_type, self.name, self.target
+            ),
+            provider => write!(
+                f,
+                "{} record {} ({})",
+                self.record_type, self.name, provider
+            ),
+        }
+    }
+}
+
+/// Whether Cloudflare accepts a TTL (1 = automatic)
+pub fn valid_ttl(ttl: u32) -> bool {
+    ttl == 1 || (60..=86400).contains(&ttl)
+}
+
+/// Whether a name is the zone apex or below it
+pub fn name_in_zone(name: &str, zone: &str) -> bool {
+    let name = name.trim_end_matches('.').to_lowercase();
+    let zone = zone.trim_end_matches('.').to_lowercase();
+    name == zone || name.ends_with(&format!(".{}", zone))
+}
+
+/// Parse a duration such as "90s", "5m" or "1h 30m"
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    humantime::parse_duration(value).with_context(|| {
+        format!(
+            "Invalid duration: {} (e.g., \"30s\", \"5m\", \"1h 30m\")",
+            value
+        )
+    })
+}
+
+/// Parse an IANA time zone name
+pub fn parse_timezone(name: &str) -> Result<chrono_tz::Tz> {
+    name.parse().map_err(|_| {
+        anyhow::anyhow!(
+            "Unknown time zone: {} (use an IANA name such as \"Europe/Berlin\")",
+            name
+        )
+    })
+}
+
+/// Parse a cron expression the way the scheduler does (6 fields, seconds first)
+pub fn parse_cron(expr: &str) -> Result<croner::Cron> {
+    croner::Cron::new(expr)
+        .with_seconds_required()
+        .with_dom_and_dow()
+        .parse()
+        .with_context(|| format!("Invalid cron expression: {}", expr))
+}
+
 pub fn default_ipv4_url() -> String {
     "https://api.ipify.org".to_string()
 }
@@ -135,14 +822,44 @@ pub fn default_ipv6_url() -> String {
 impl Config {
     /// Load configuration from a TOML file
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let path = path.as_ref();
-        let content = fs::read_to_string(path)
-            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+        let config = Self::read(path.as_ref())?;
+        config.validate()?;
+        Ok(config)
+    }
 
-        let config: Config = toml::from_str(&content)
-            .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+    /// Read a config file or directory without validating it
+    pub fn read(path: &Path) -> Result<Self> {
+        Self::from_value(merge_files(&config_files(path)?)?, path)
+    }
 
+    /// Load a config file from `content` instead of what is on disk
+    ///
+    /// For checking an edit before it is written.
+    pub fn parse(path: &Path, content: &str) -> Result<Self> {
+        let config = Self::from_value(merge_texts(&[(path, content.to_string())])?, path)?;
         config.validate()?;
+        Ok(config)
+    }
+
+    /// Build the config from merged files, resolving references and the token
+    fn from_value(mut value: toml::Value, path: &Path) -> Result<Self> {
+        // Remember what was written so resolved secrets are never saved back
+        let mut references = Vec::new();
+        interpolate_env(&mut value, &mut Vec::new(), &mut references)?;
+
+        let mut config: Config = value
+            .try_into()
+            .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+        config.references = references;
+
+        let (token, written) = (
+            config.cloudflare.api_token.clone(),
+            config.written_token().to_string(),
+        );
+        config.cloudflare.resolve_token()?;
+        if config.cloudflare.api_token != token {
+            config.keep_written_token(written);
+        }
 
         Ok(config)
     }
@@ -158,20 +875,37 @@ impl Config {
         force_ip: Option<IpAddr>,
     ) -> Result<Self> {
         let config = Config {
-            cloudflare: CloudflareConfig { api_token },
+            version: CONFIG_VERSION,
+            cloudflare: CloudflareConfig {
+                api_token,
+                ..Default::default()
+            },
+            rfc2136: None,
+            webhooks: BTreeMap::new(),
+            ip_lists: Vec::new(),
+            origins: Vec::new(),
             records: vec![RecordConfig {
                 zone,
                 name: record_name,
                 record_type,
                 proxied,
                 ttl,
+                provider: Provider::Cloudflare,
+                webhook: None,
+                content: None,
+                content_rules: Vec::new(),
+                ip_version: IpVersion::default(),
+                comment: None,
+                tags: Vec::new(),
+                cron: None,
+                interval: None,
             }],
             settings: Settings {
-                ipv4_url: default_ipv4_url(),
-                ipv6_url: default_ipv6_url(),
                 force_ip,
+                ..Default::default()
             },
             service: ServiceConfig::default(),
+            references: Vec::new(),
         };
 
         config.validate()?;
@@ -180,34 +914,1464 @@ impl Config {
 
     /// Validate the configuration
     pub fn validate(&self) -> Result<()> {
-        if self.cloudflare.api_token.is_empty() {
-            anyhow::bail!("Cloudflare API token cannot be empty");
+        if self.version > CONFIG_VERSION {
+            anyhow::bail!(
+                "Config version {} is newer than this cddns supports ({})",
+                self.version,
+                CONFIG_VERSION
+            );
         }
 
-        if self.records.is_empty() {
-            anyhow::bail!("At least one DNS record must be configured");
+        if self.records.is_empty() && self.targets().next().is_none() {
+            anyhow::bail!("At least one DNS record, IP list or origin must be configured");
         }
 
-        for record in &self.records {
-            if record.zone.is_empty() {
-                anyhow::bail!("Record zone cannot be empty");
+        if (self.uses_provider(Provider::Cloudflare) || self.targets().next().is_some())
+            && self.cloudflare.api_token.is_empty()
+        {
+            anyhow::bail!(
+                "Cloudflare API token cannot be empty (set api_token, api_token_file or CF_API_TOKEN)"
+            );
+        }
+
+        if self.cloudflare.api_token_file.is_some() && !self.written_token().is_empty() {
+            anyhow::bail!("Set either api_token or api_token_file, not both");
+        }
+
+        if self.uses_provider(Provider::Rfc2136) {
+            match &self.rfc2136 {
+                Some(rfc2136) => {
+                    if rfc2136.server.is_empty() {
+                        anyhow::bail!("RFC 2136 server cannot be empty");
+                    }
+                    if rfc2136.key_name.is_empty() || rfc2136.key_secret.is_empty() {
+                        anyhow::bail!("RFC 2136 TSIG key name and secret must be set");
+                    }
+                }
+                None => {
+                    anyhow::bail!(
+                        "Records use the rfc2136 provider but no [rfc2136] section is configured"
+                    )
+                }
+            }
+        }
+
+        if self.settings.prune_orphans && !self.settings.mark_managed {
+            anyhow::bail!(
+                "prune_orphans requires mark_managed so cddns can tell which records it owns"
+            );
+        }
+        if self.settings.owner_id.is_empty()
+            || !self
+                .settings
+                .owner_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+        {
+            anyhow::bail!(
+                "Invalid owner_id {:?}: use letters, digits, '.', '_' and '-'",
+                self.settings.owner_id
+            );
+        }
+        if self.settings.concurrency == 0 {
+            anyhow::bail!("concurrency must be at least 1");
+        }
+
+        // Shared with `cddns validate`, which reports every problem with its location
+        let service = rules::check_service(&self.service);
+        let records = self.records.iter().flat_map(|record| {
+            rules::check_record(record, &self.service, |name| {
+                self.webhooks.contains_key(name)
+            })
+        });
+        for problem in records.chain(service) {
+            if problem.severity == Severity::Error {
+                anyhow::bail!(problem.message);
             }
-            if record.name.is_empty() {
-                anyhow::bail!("Record name cannot be empty");
+        }
+
+        if let Some(dyndns) = &self.service.dyndns {
+            if dyndns.username.is_empty() || dyndns.password.is_empty() {
+                anyhow::bail!("dyndns listener username and password must be set");
+            }
+        }
+
+        for (name, webhook) in &self.webhooks {
+            if webhook.url.is_empty() {
+                anyhow::bail!("Webhook {} URL cannot be empty", name);
+            }
+            if reqwest::Method::from_bytes(webhook.method.to_uppercase().as_bytes()).is_err() {
+                anyhow::bail!("Webhook {} has an invalid method: {}", name, webhook.method);
+            }
+        }
+
+        for list in &self.ip_lists {
+            if list.account_id.is_empty() || list.list.is_empty() {
+                anyhow::bail!("IP list account_id and list name cannot be empty");
+            }
+            if list.comment.is_empty() {
+                anyhow::bail!(
+                    "IP list {} needs a comment to identify its entry",
+                    list.list
+                );
             }
         }
 
         Ok(())
     }
 
+    /// Every distinct schedule, the service schedule first
+    pub fn schedules(&self) -> Result<Vec<Schedule>> {
+        let mut schedules = vec![self.service.schedule()?];
+        for record in &self.records {
+            let schedule = record.schedule(&self.service)?;
+            if !schedules.contains(&schedule) {
+                schedules.push(schedule);
+            }
+        }
+        Ok(schedules)
+    }
+
+    /// Records updated on the given schedule
+    pub fn records_on<'a>(
+        &'a self,
+        schedule: &'a Schedule,
+    ) -> impl Iterator<Item = &'a RecordConfig> + 'a {
+        self.records
+            .iter()
+            .filter(move |record| record.schedule(&self.service).is_ok_and(|s| &s == schedule))
+    }
+
+    /// IP lists and origins to keep at the dynamic IP
+    pub fn targets(&self) -> impl Iterator<Item = Target<'_>> {
+        self.ip_lists
+            .iter()
+            .map(Target::IpList)
+            .chain(self.origins.iter().map(Target::Origin))
+    }
+
+    /// `api_token` as written in the file, before it was resolved
+    pub fn written_token(&self) -> &str {
+        self.references
+            .iter()
+            .find(|reference| reference.path == token_path())
+            .map_or(&self.cloudflare.api_token, |reference| &reference.written)
+    }
+
+    /// Save `api_token` as `written` (e.g., empty or `${VAR}`) while it holds the resolved token
+    pub fn keep_written_token(&mut self, written: String) {
+        let path = token_path();
+        self.references.retain(|reference| reference.path != path);
+        self.references.push(Reference {
+            path,
+            written,
+            resolved: self.cloudflare.api_token.clone(),
+        });
+    }
+
+    /// Serialize for saving, with references in place of the values they resolved to
+    fn written_table(&self) -> Result<toml::Table> {
+        let mut table = to_table(self)?;
+        for reference in &self.references {
+            if let Some(value) = lookup(&mut table, &reference.path) {
+                if value.as_str() == Some(reference.resolved.as_str()) {
+                    *value = toml::Value::String(reference.written.clone());
+                }
+            }
+        }
+        Ok(table)
+    }
+
+    /// Whether any record is managed by the given provider
+    pub fn uses_provider(&self, provider: Provider) -> bool {
+        self.records.iter().any(|r| r.provider == provider)
+    }
+
     /// Save configuration to a TOML file
+    ///
+    /// An existing file is edited in place: only keys that differ from what
+    /// the file currently loads as are written, so comments and layout survive.
     pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         let path = path.as_ref();
-        let content = toml::to_string_pretty(self).context("Failed to serialize config")?;
+        let content = self.file_content(path)?;
+        write_atomic(path, &content)
+    }
 
-        fs::write(path, content)
-            .with_context(|| format!("Failed to write config file: {}", path.display()))?;
+    /// Text that `save` writes to `path`
+    pub fn file_content(&self, path: &Path) -> Result<String> {
+        if path.is_dir() {
+            anyhow::bail!(
+                "{} is a config directory; edit its files directly",
+                path.display()
+            );
+        }
 
-        Ok(())
+        let content = if path.exists() {
+            self.edit_file(path)?
+        } else {
+            let mut document: toml_edit::DocumentMut = toml::to_string_pretty(self)
+                .context("Failed to serialize config")?
+                .parse()
+                .context("Failed to convert config")?;
+            update_table(
+                document.as_table_mut(),
+                &to_table(self)?,
+                &self.written_table()?,
+                "",
+            )?;
+            document.to_string()
+        };
+
+        Ok(content)
+    }
+
+    /// Apply the differences from the config on disk to its document
+    fn edit_file(&self, path: &Path) -> Result<String> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+        let mut document: toml_edit::DocumentMut = content
+            .parse()
+            .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+        migrate_document(&mut document)?;
+
+        let current = Self::read(path).with_context(|| {
+            format!(
+                "Failed to load {} to merge changes into; fix or move it first",
+                path.display()
+            )
+        })?;
+        let old = current.written_table()?;
+        let new = self.written_table()?;
+        update_table(document.as_table_mut(), &old, &new, "")?;
+
+        Ok(document.to_string())
+    }
+}
+
+/// Replace a file by renaming a finished copy over it
+///
+/// Readers such as the service's file watcher see the old or the new content,
+/// never a partly written file. An existing file keeps its permissions.
+pub fn write_atomic(path: &Path, content: &str) -> Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+
+    fs::write(&temp, content)
+        .with_context(|| format!("Failed to write config file: {}", temp.display()))?;
+    let result = match fs::metadata(path) {
+        Ok(metadata) => fs::set_permissions(&temp, metadata.permissions()),
+        Err(_) => Ok(()),
+    }
+    .and_then(|()| fs::rename(&temp, path));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp);
+        return Err(e).with_context(|| format!("Failed to write config file: {}", path.display()));
+    }
+    Ok(())
+}
+
+/// Serialize a config into a TOML table
+fn to_table(config: &Config) -> Result<toml::Table> {
+    match toml::Value::try_from(config).context("Failed to serialize config")? {
+        toml::Value::Table(table) => Ok(table),
+        _ => unreachable!("config serializes to a table"),
+    }
+}
+
+/// Write the keys that changed between `old` and `new` into a document table
+///
+/// Keys that only exist in the loaded config (defaults, `[defaults]`,
+/// `[[zones]]`) are added to the document when they change.
+fn update_table(
+    table: &mut dyn toml_edit::TableLike,
+    old: &toml::Table,
+    new: &toml::Table,
+    key_path: &str,
+) -> Result<()> {
+    for (key, new_value) in new {
+        let old_value = old.get(key);
+        if old_value == Some(new_value) {
+            continue;
+        }
+        let child_path = if key_path.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", key_path, key)
+        };
+
+        match (table.get_mut(key), old_value, new_value) {
+            (Some(item), Some(toml::Value::Table(old)), toml::Value::Table(new))
+                if item.is_table_like() =>
+            {
+                let child = item.as_table_like_mut().expect("checked table-like");
+                update_table(child, old, new, &child_path)?;
+            }
+            (
+                Some(toml_edit::Item::ArrayOfTables(tables)),
+                Some(toml::Value::Array(old)),
+                toml::Value::Array(new),
+            ) => update_tables(tables, old, new, &child_path)?,
+            (Some(item), _, _) => set_item(item, new_value)?,
+            (None, Some(toml::Value::Table(old)), toml::Value::Table(new)) => {
+                let mut child = toml_edit::Table::new();
+                update_table(&mut child, old, new, &child_path)?;
+                table.insert(key, toml_edit::Item::Table(child));
+            }
+            (None, Some(toml::Value::Array(old)), toml::Value::Array(new))
+                if is_table_array(old) && is_table_array(new) =>
+            {
+                let mut tables = toml_edit::ArrayOfTables::new();
+                update_tables(&mut tables, old, new, &child_path)?;
+                if !tables.is_empty() {
+                    table.insert(key, toml_edit::Item::ArrayOfTables(tables));
+                }
+            }
+            (None, _, _) => {
+                table.insert(key, to_item(new_value)?);
+            }
+        }
+    }
+
+    let removed: Vec<&String> = old.keys().filter(|key| !new.contains_key(*key)).collect();
+    for key in removed {
+        table.remove(key);
+    }
+    Ok(())
+}
+
+/// Write changed entries of an array of tables, matched by position
+///
+/// Entries past the end of the document's array come from `[[zones]]`
+/// groups and can't be edited from here.
+fn update_tables(
+    tables: &mut toml_edit::ArrayOfTables,
+    old: &[toml::Value],
+    new: &[toml::Value],
+    key_path: &str,
+) -> Result<()> {
+    let written = tables.len();
+    for (i, new_value) in new.iter().enumerate() {
+        let old_value = old.get(i);
+        if old_value == Some(new_value) {
+            continue;
+        }
+        let toml::Value::Table(new_table) = new_value else {
+            anyhow::bail!("{}[{}] must be a table", key_path, i);
+        };
+
+        match old_value {
+            Some(toml::Value::Table(old_table)) if i < written => {
+                let table = tables.get_mut(i).expect("index within array");
+                update_table(table, old_table, new_table, &format!("{}[{}]", key_path, i))?;
+            }
+            Some(_) => anyhow::bail!(
+                "{}[{}] is generated from a [[zones]] group; edit it in the file directly",
+                key_path,
+                i
+            ),
+            None => tables.push(to_table_item(new_table)?),
+        }
+    }
+
+    if old.len() > new.len() {
+        if old.len() > written {
+            anyhow::bail!(
+                "Can't remove {} entries generated from [[zones]] groups; edit the file directly",
+                key_path
+            );
+        }
+        for i in (new.len()..old.len()).rev() {
+            tables.remove(i);
+        }
+    }
+    Ok(())
+}
+
+/// Replace a document item, keeping the comments around plain values
+fn set_item(item: &mut toml_edit::Item, value: &toml::Value) -> Result<()> {
+    let replacement = to_item(value)?;
+    match (item.as_value_mut(), replacement) {
+        (Some(current), toml_edit::Item::Value(mut new)) => {
+            *new.decor_mut() = current.decor().clone();
+            *current = new;
+        }
+        (_, replacement) => *item = replacement,
+    }
+    Ok(())
+}
+
+fn is_table_array(values: &[toml::Value]) -> bool {
+    !values.is_empty() && values.iter().all(toml::Value::is_table)
+}
+
+/// Convert a value into a document item, using `[table]` and `[[array]]` syntax
+fn to_item(value: &toml::Value) -> Result<toml_edit::Item> {
+    match value {
+        toml::Value::Table(table) => Ok(toml_edit::Item::Table(to_table_item(table)?)),
+        toml::Value::Array(values) if is_table_array(values) => {
+            let mut tables = toml_edit::ArrayOfTables::new();
+            for value in values {
+                if let toml::Value::Table(table) = value {
+                    tables.push(to_table_item(table)?);
+                }
+            }
+            Ok(toml_edit::Item::ArrayOfTables(tables))
+        }
+        _ => {
+            let value = value
+                .to_string()
+                .parse::<toml_edit::Value>()
+                .context("Failed to convert config value")?;
+            Ok(toml_edit::value(value))
+        }
+    }
+}
+
+fn to_table_item(table: &toml::Table) -> Result<toml_edit::Table> {
+    let document: toml_edit::DocumentMut = toml::to_string(table)
+        .context("Failed to serialize config table")?
+        .parse()
+        .context("Failed to convert config table")?;
+    Ok(document.as_table().clone())
+}
+
+/// Expand `${VAR}` references in every string value of the config
+///
+/// `$${` produces a literal `${`. Every expanded value is added to `references`.
+fn interpolate_env(
+    value: &mut toml::Value,
+    path: &mut Vec<KeyPart>,
+    references: &mut Vec<Reference>,
+) -> Result<()> {
+    match value {
+        toml::Value::String(s) if s.contains("${") => {
+            let resolved =
+                expand_env(s).with_context(|| format!("Failed to expand {}", key_path(path)))?;
+            references.push(Reference {
+                path: path.clone(),
+                written: std::mem::replace(s, resolved.clone()),
+                resolved,
+            });
+        }
+        toml::Value::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                path.push(KeyPart::Index(i));
+                interpolate_env(item, path, references)?;
+                path.pop();
+            }
+        }
+        toml::Value::Table(table) => {
+            for (key, item) in table.iter_mut() {
+                path.push(KeyPart::Key(key.clone()));
+                interpolate_env(item, path, references)?;
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Path of a value for messages, e.g. "webhooks.duckdns.headers.Authorization"
+fn key_path(path: &[KeyPart]) -> String {
+    let mut out = String::new();
+    for part in path {
+        match part {
+            KeyPart::Key(key) if out.is_empty() => out.push_str(key),
+            KeyPart::Key(key) => {
+                out.push('.');
+                out.push_str(key);
+            }
+            KeyPart::Index(i) => out.push_str(&format!("[{}]", i)),
+        }
+    }
+    out
+}
+
+/// The value at a path, if it is still there
+fn lookup<'a>(table: &'a mut toml::Table, path: &[KeyPart]) -> Option<&'a mut toml::Value> {
+    let (KeyPart::Key(first), rest) = path.split_first()? else {
+        return None;
+    };
+    let mut value = table.get_mut(first)?;
+    for part in rest {
+        value = match part {
+            KeyPart::Key(key) => value.as_table_mut()?.get_mut(key)?,
+            KeyPart::Index(i) => value.as_array_mut()?.get_mut(*i)?,
+        };
+    }
+    Some(value)
+}
+
+fn token_path() -> Vec<KeyPart> {
+    vec![
+        KeyPart::Key("cloudflare".to_string()),
+        KeyPart::Key("api_token".to_string()),
+    ]
+}
+
+/// Replace `${VAR}` with the value of the environment variable `VAR`
+pub fn expand_env(input: &str) -> Result<String> {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let tail = &rest[start..];
+
+        if let Some(after) = tail.strip_prefix("$${") {
+            out.push_str("${");
+            rest = after;
+        } else if let Some(after) = tail.strip_prefix("${") {
+            let end = after
+                .find('}')
+                .with_context(|| format!("Unterminated variable reference in \"{}\"", input))?;
+            let name = &after[..end];
+            let value = std::env::var(name)
+                .ok()
+                .with_context(|| format!("Environment variable {} is not set", name))?;
+            out.push_str(&value);
+            rest = &after[end + 1..];
+        } else {
+            out.push('$');
+            rest = &tail[1..];
+        }
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Remove the `[defaults]` table from a config file
+fn take_defaults(root: &mut toml::Table) -> Result<toml::Table> {
+    let defaults = match root.remove("defaults") {
+        Some(toml::Value::Table(defaults)) => defaults,
+        Some(_) => anyhow::bail!("[defaults] must be a table"),
+        None => toml::Table::new(),
+    };
+    if defaults.contains_key("name") {
+        anyhow::bail!("[defaults] cannot set a record name");
+    }
+    Ok(defaults)
+}
+
+/// Expand `[[zones]]` groups into plain `[[records]]` entries
+///
+/// Zone entries take a list of short names (or inline tables with a `name`),
+/// expanded to FQDNs inside the zone. Other keys of a zone entry apply to all
+/// of its records; `defaults` fills keys that neither the record nor its
+/// zone set.
+fn expand_records(root: &mut toml::Table, defaults: &toml::Table) -> Result<()> {
+    let mut records = match root.remove("records") {
+        Some(toml::Value::Array(records)) => records,
+        Some(_) => anyhow::bail!("records must be an array of tables ([[records]])"),
+        None => Vec::new(),
+    };
+
+    let zones = match root.remove("zones") {
+        Some(toml::Value::Array(zones)) => zones,
+        Some(_) => anyhow::bail!("zones must be an array of tables ([[zones]])"),
+        None => Vec::new(),
+    };
+
+    for zone in zones {
+        let toml::Value::Table(mut zone) = zone else {
+            anyhow::bail!("zones must be an array of tables ([[zones]])");
+        };
+        let zone_name = match zone.remove("name") {
+            Some(toml::Value::String(name)) => name,
+            _ => anyhow::bail!("Every [[zones]] entry needs a name"),
+        };
+        let entries = match zone.remove("records") {
+            Some(toml::Value::Array(entries)) => entries,
+            _ => anyhow::bail!("Zone {} needs a records list", zone_name),
+        };
+
+        for entry in entries {
+            let mut record = zone.clone();
+            let short = match entry {
+                toml::Value::String(short) => short,
+                toml::Value::Table(overrides) => {
+                    let short = match overrides.get("name") {
+                        Some(toml::Value::String(short)) => short.clone(),
+                        _ => anyhow::bail!("Record in zone {} is missing a name", zone_name),
+                    };
+                    record.extend(overrides);
+                    short
+                }
+                _ => anyhow::bail!(
+                    "Records in zone {} must be names or inline tables",
+                    zone_name
+                ),
+            };
+
+            record.insert(
+                "name".to_string(),
+                toml::Value::String(qualify_name(&short, &zone_name)),
+            );
+            record.insert("zone".to_string(), toml::Value::String(zone_name.clone()));
+            records.push(toml::Value::Table(record));
+        }
+    }
+
+    for record in &mut records {
+        if let toml::Value::Table(record) = record {
+            for (key, value) in defaults {
+                record.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+        }
+    }
+
+    root.insert("records".to_string(), toml::Value::Array(records));
+    Ok(())
+}
+
+/// Expand a short record name to an FQDN inside the zone
+///
+/// "@" is the zone apex; names already ending in the zone or with a trailing
+/// dot are kept as they are.
+pub fn qualify_name(name: &str, zone: &str) -> String {
+    let zone = zone.trim_end_matches('.');
+    if name == "@" {
+        return zone.to_string();
+    }
+    if let Some(absolute) = name.strip_suffix('.') {
+        return absolute.to_string();
+    }
+
+    let lower = name.to_lowercase();
+    let zone_lower = zone.to_lowercase();
+    if lower == zone_lower || lower.ends_with(&format!(".{}", zone_lower)) {
+        name.to_string()
+    } else {
+        format!("{}.{}", name, zone)
+    }
+}
+
+/// Files making up a config: the file itself, or a directory's base config
+/// and drop-in fragments in merge order
+pub fn config_files(path: &Path) -> Result<Vec<PathBuf>> {
+    if path.is_dir() {
+        config_dir_files(path)
+    } else {
+        Ok(vec![path.to_path_buf()])
+    }
+}
+
+/// Base config and drop-in fragments of a config directory, in merge order
+fn config_dir_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    let base = dir.join("config.toml");
+    if base.is_file() {
+        files.push(base);
+    }
+
+    let fragments_dir = dir.join("conf.d");
+    if fragments_dir.is_dir() {
+        let mut fragments: Vec<PathBuf> = fs::read_dir(&fragments_dir)
+            .with_context(|| format!("Failed to read {}", fragments_dir.display()))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        fragments.sort();
+        files.extend(fragments);
+    }
+
+    if files.is_empty() {
+        anyhow::bail!("No config.toml or conf.d/*.toml found in {}", dir.display());
+    }
+    Ok(files)
+}
+
+/// Read config files and merge them into one document
+///
+/// Later files add records and override values of the first (base) file.
+/// Two fragments setting the same value differently, or defining the same
+/// record, is an error naming both files.
+fn merge_files(files: &[PathBuf]) -> Result<toml::Value> {
+    let mut texts = Vec::with_capacity(files.len());
+    for path in files {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+        texts.push((path.as_path(), content));
+    }
+    merge_texts(&texts)
+}
+
+/// Merge the contents of config files, the base file first
+fn merge_texts(texts: &[(&Path, String)]) -> Result<toml::Value> {
+    let mut documents = Vec::with_capacity(texts.len());
+    for (path, content) in texts {
+        let mut document: toml_edit::DocumentMut = content
+            .parse()
+            .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+        let changes = migrate_document(&mut document)
+            .with_context(|| format!("Failed to migrate config file: {}", path.display()))?;
+        if !changes.is_empty() {
+            warn!(
+                "{} uses an older config layout ({}); run 'cddns config migrate' to update it",
+                path.display(),
+                changes.join(", ")
+            );
+        }
+
+        let value: toml::Value = toml::from_str(&document.to_string())
+            .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+        let toml::Value::Table(table) = value else {
+            anyhow::bail!("Config file is not a table: {}", path.display());
+        };
+        documents.push((*path, table));
+    }
+
+    let base = texts.first().map(|(path, _)| *path);
+    let mut sources = BTreeMap::new();
+
+    // Defaults apply to the records of every file
+    let mut defaults = toml::Table::new();
+    for (path, table) in &mut documents {
+        let file_defaults = take_defaults(table)
+            .with_context(|| format!("Invalid config file: {}", path.display()))?;
+        merge_tables(
+            &mut defaults,
+            file_defaults,
+            "defaults",
+            path,
+            base,
+            &mut sources,
+        )?;
+    }
+
+    let mut merged = toml::Table::new();
+    let mut record_sources: BTreeMap<RecordKey, &Path> = BTreeMap::new();
+    for (path, mut table) in documents {
+        expand_records(&mut table, &defaults)
+            .with_context(|| format!("Invalid config file: {}", path.display()))?;
+
+        if let Some(toml::Value::Array(records)) = table.get("records") {
+            for record in records {
+                let field = |key: &str| record.get(key).and_then(|v| v.as_str());
+                let key = RecordKey::new(
+                    field("provider"),
+                    field("zone").unwrap_or_default(),
+                    field("webhook"),
+                    field("name").unwrap_or_default(),
+                    field("record_type"),
+                );
+                let duplicate = key.to_string();
+                if let Some(first) = record_sources.insert(key, path) {
+                    if first == path {
+                        anyhow::bail!("Duplicate {} in {}", duplicate, path.display());
+                    }
+                    anyhow::bail!(
+                        "Duplicate {} in {} and {}",
+                        duplicate,
+                        first.display(),
+                        path.display()
+                    );
+                }
+            }
+        }
+
+        merge_tables(&mut merged, table, "", path, base, &mut sources)?;
+    }
+
+    Ok(toml::Value::Table(merged))
+}
+
+/// Top-level lists that fragments append to instead of replacing
+const APPENDED_LISTS: [&str; 3] = ["records", "ip_lists", "origins"];
+
+/// Merge `fragment` into `target`, tracking which file set each value
+fn merge_tables<'a>(
+    target: &mut toml::Table,
+    fragment: toml::Table,
+    key_path: &str,
+    source: &'a Path,
+    base: Option<&Path>,
+    sources: &mut BTreeMap<String, &'a Path>,
+) -> Result<()> {
+    for (key, value) in fragment {
+        let path = if key_path.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", key_path, key)
+        };
+
+        match (target.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(table)) => {
+                merge_tables(existing, table, &path, source, base, sources)?;
+            }
+            (Some(toml::Value::Array(existing)), toml::Value::Array(items))
+                if key_path.is_empty() && APPENDED_LISTS.contains(&key.as_str()) =>
+            {
+                existing.extend(items);
+            }
+            (Some(existing), value) => {
+                let previous = source_of(sources, &path);
+                if previous != base && *existing != value {
+                    anyhow::bail!(
+                        "{} is set differently in {} and {}",
+                        path,
+                        previous
+                            .map(|p| p.display().to_string())
+                            .unwrap_or_default(),
+                        source.display()
+                    );
+                }
+                *existing = value;
+                sources.insert(path, source);
+            }
+            (None, value) => {
+                target.insert(key, value);
+                sources.insert(path, source);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// File that set a value, or the table containing it
+fn source_of<'a>(sources: &BTreeMap<String, &'a Path>, path: &str) -> Option<&'a Path> {
+    let mut path = path;
+    loop {
+        if let Some(source) = sources.get(path) {
+            return Some(source);
+        }
+        path = &path[..path.rfind('.')?];
+    }
+}
+
+/// Upgrade steps; entry `i` migrates version `i + 1` to `i + 2`
+type Migration = fn(&mut toml_edit::DocumentMut) -> Vec<String>;
+const MIGRATIONS: [Migration; 1] = [migrate_v1_cron];
+
+/// Upgrade a config document to the current layout in place
+///
+/// Returns a description of each change made, not counting the version bump.
+pub fn migrate_document(document: &mut toml_edit::DocumentMut) -> Result<Vec<String>> {
+    let version = match document.get("version") {
+        Some(item) => item
+            .as_integer()
+            .and_then(|v| u32::try_from(v).ok())
+            .filter(|v| *v >= 1)
+            .context("version must be a positive integer")?,
+        None => default_version(),
+    };
+    if version > CONFIG_VERSION {
+        anyhow::bail!(
+            "Config version {} is newer than this cddns supports ({})",
+            version,
+            CONFIG_VERSION
+        );
+    }
+
+    let mut changes = Vec::new();
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        changes.extend(migration(document));
+    }
+
+    if version < CONFIG_VERSION {
+        document["version"] = toml_edit::value(i64::from(CONFIG_VERSION));
+    }
+    Ok(changes)
+}
+
+/// Version 1 -> 2: cron expressions gained a leading seconds field
+///
+/// `cron` can be set in `[service]`, `[defaults]`, records, zone groups and
+/// the inline records of a zone group.
+fn migrate_v1_cron(document: &mut toml_edit::DocumentMut) -> Vec<String> {
+    let root = document.as_table_mut();
+    let mut changes = Vec::new();
+
+    for key in ["service", "defaults"] {
+        if let Some(table) = root.get_mut(key).and_then(|item| item.as_table_like_mut()) {
+            changes.extend(upgrade_cron(table, key));
+        }
+    }
+    if let Some(records) = root.get_mut("records") {
+        for (i, record) in tables_in(records) {
+            changes.extend(upgrade_cron(record, &format!("records[{}]", i)));
+        }
+    }
+    if let Some(zones) = root.get_mut("zones") {
+        for (i, zone) in tables_in(zones) {
+            let path = format!("zones[{}]", i);
+            changes.extend(upgrade_cron(zone, &path));
+            if let Some(records) = zone.get_mut("records") {
+                for (j, record) in tables_in(records) {
+                    changes.extend(upgrade_cron(record, &format!("{}.records[{}]", path, j)));
+                }
+            }
+        }
+    }
+    changes
+}
+
+/// Tables of an array of tables or an array of inline tables, with their index
+fn tables_in(item: &mut toml_edit::Item) -> Vec<(usize, &mut dyn toml_edit::TableLike)> {
+    match item {
+        toml_edit::Item::ArrayOfTables(tables) => tables
+            .iter_mut()
+            .map(|table| table as &mut dyn toml_edit::TableLike)
+            .enumerate()
+            .collect(),
+        toml_edit::Item::Value(toml_edit::Value::Array(values)) => values
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, value)| {
+                let table = value.as_inline_table_mut()?;
+                Some((i, table as &mut dyn toml_edit::TableLike))
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Prepend a seconds field to a 5-field `cron` in the table
+fn upgrade_cron(table: &mut dyn toml_edit::TableLike, path: &str) -> Option<String> {
+    let cron = table.get_mut("cron")?.as_value_mut()?;
+    let expr = cron.as_str()?;
+    if expr.split_whitespace().count() != 5 {
+        return None;
+    }
+
+    let upgraded = format!("0 {}", expr.trim());
+    let change = format!("{}.cron \"{}\" -> \"{}\"", path, expr, upgraded);
+    let decor = cron.decor().clone();
+    *cron = upgraded.into();
+    *cron.decor_mut() = decor;
+    Some(change)
+}
+
+/// Migrate one config file in place, keeping the original as `<file>.bak`
+///
+/// Returns the changes made and the backup path, or `None` if the file is
+/// already up to date.
+pub fn migrate_file(file: &Path) -> Result<Option<(Vec<String>, PathBuf)>> {
+    let content = fs::read_to_string(file)
+        .with_context(|| format!("Failed to read config file: {}", file.display()))?;
+    let mut document: toml_edit::DocumentMut = content
+        .parse()
+        .with_context(|| format!("Failed to parse config file: {}", file.display()))?;
+
+    let changes = migrate_document(&mut document)
+        .with_context(|| format!("Failed to migrate config file: {}", file.display()))?;
+    let migrated = document.to_string();
+    if migrated == content {
+        return Ok(None);
+    }
+
+    let mut backup = file.to_path_buf().into_os_string();
+    backup.push(".bak");
+    let backup = PathBuf::from(backup);
+    fs::copy(file, &backup).with_context(|| format!("Failed to back up {}", file.display()))?;
+    fs::write(file, migrated)
+        .with_context(|| format!("Failed to write config file: {}", file.display()))?;
+    Ok(Some((changes, backup)))
+}
+
+/// JSON Schema for config files, for editor completion and CI linting
+///
+/// Derived from [`Config`], plus the `[defaults]` and `[[zones]]` sections
+/// that are expanded into records on load.
+pub fn json_schema() -> serde_json::Value {
+    let mut schema = serde_json::to_value(schemars::schema_for!(Config))
+        .expect("config schema serializes to JSON");
+    schema["title"] = "cddns configuration".into();
+
+    let definitions = &mut schema["definitions"];
+
+    // Zone and name may come from [defaults] or a zone group
+    definitions["RecordConfig"]["required"] = serde_json::json!(["name"]);
+
+    let mut defaults = definitions["RecordConfig"].clone();
+    if let Some(object) = defaults.as_object_mut() {
+        object.remove("required");
+        object.insert(
+            "description".to_string(),
+            "Values applied to every record that doesn't set them".into(),
+        );
+    }
+    if let Some(properties) = defaults["properties"].as_object_mut() {
+        properties.remove("name");
+    }
+
+    let mut zone = defaults.clone();
+    zone["description"] = "Records in one zone, with short names expanded to FQDNs".into();
+    zone["required"] = serde_json::json!(["name", "records"]);
+    zone["properties"]["name"] = serde_json::json!({
+        "description": "The zone name (e.g., \"example.com\")",
+        "type": "string"
+    });
+    zone["properties"]["records"] = serde_json::json!({
+        "description": "Short names (\"home\", \"@\" for the apex) or inline tables with a name",
+        "type": "array",
+        "items": {
+            "anyOf": [
+                { "type": "string" },
+                { "$ref": "#/definitions/RecordConfig" }
+            ]
+        }
+    });
+    if let Some(properties) = zone["properties"].as_object_mut() {
+        properties.remove("zone");
+    }
+
+    definitions["RecordDefaults"] = defaults;
+    definitions["ZoneGroup"] = zone;
+
+    schema["properties"]["defaults"] = serde_json::json!({
+        "$ref": "#/definitions/RecordDefaults"
+    });
+    schema["properties"]["zones"] = serde_json::json!({
+        "description": "Record groups per zone",
+        "type": "array",
+        "items": { "$ref": "#/definitions/ZoneGroup" }
+    });
+
+    schema
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty directory for the files of one test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cddns-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn expands_variables() {
+        std::env::set_var("CDDNS_TEST_EXPAND", "secret");
+        assert_eq!(
+            expand_env("Bearer ${CDDNS_TEST_EXPAND}!").unwrap(),
+            "Bearer secret!"
+        );
+        assert_eq!(expand_env("no references").unwrap(), "no references");
+    }
+
+    #[test]
+    fn keeps_escaped_references() {
+        assert_eq!(
+            expand_env("$${CDDNS_TEST_UNSET} costs $5").unwrap(),
+            "${CDDNS_TEST_UNSET} costs $5"
+        );
+    }
+
+    #[test]
+    fn rejects_unterminated_references() {
+        let error = expand_env("Bearer ${CDDNS_TEST_EXPAND").unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Unterminated variable reference"));
+    }
+
+    #[test]
+    fn rejects_unset_variables() {
+        let error = expand_env("${CDDNS_TEST_UNSET}").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Environment variable CDDNS_TEST_UNSET is not set"
+        );
+    }
+
+    #[test]
+    fn rejects_token_and_token_file() {
+        let dir = temp_dir("token-file");
+        fs::write(dir.join("token"), "from-file\n").unwrap();
+        let path = dir.join("config.toml");
+        fs::write(
+            &path,
+            format!(
+                "[cloudflare]\napi_token = \"inline\"\napi_token_file = {:?}\n\n\
+                 [[records]]\nzone = \"example.com\"\nname = \"home.example.com\"\n",
+                dir.join("token")
+            ),
+        )
+        .unwrap();
+
+        let error = Config::load(&path).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Set either api_token or api_token_file, not both"
+        );
+    }
+
+    #[test]
+    fn saves_references_instead_of_their_values() {
+        std::env::set_var("CF_TOKEN", "secret-token");
+        std::env::set_var("CDDNS_TEST_COMMENT", "router");
+        let dir = temp_dir("references");
+        let path = dir.join("config.toml");
+        fs::write(
+            &path,
+            "[cloudflare]\napi_token = \"${CF_TOKEN}\"\n\n\
+             [[records]]\nzone = \"example.com\"\nname = \"home.example.com\"\n\
+             comment = \"${CDDNS_TEST_COMMENT}\"\n",
+        )
+        .unwrap();
+
+        let mut config = Config::load(&path).unwrap();
+        assert_eq!(config.cloudflare.api_token, "secret-token");
+        assert_eq!(config.records[0].comment.as_deref(), Some("router"));
+
+        config.records[0].ttl = 300;
+        config.save(&path).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(saved.contains("api_token = \"${CF_TOKEN}\""), "{}", saved);
+        assert!(
+            saved.contains("comment = \"${CDDNS_TEST_COMMENT}\""),
+            "{}",
+            saved
+        );
+        assert!(saved.contains("ttl = 300"), "{}", saved);
+        assert!(!saved.contains("secret-token"), "{}", saved);
+
+        // A changed value replaces its reference
+        config.records[0].comment = Some("office".to_string());
+        config.save(&path).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(saved.contains("comment = \"office\""), "{}", saved);
+        assert!(saved.contains("api_token = \"${CF_TOKEN}\""), "{}", saved);
+    }
+
+    const V1_CRONS: &str = r#"# Home network
+[service]
+cron = "*/5 * * * *" # every five minutes
+
+[defaults]
+cron = "0 * * * *"
+
+[[records]]
+zone = "example.com"
+name = "a.example.com"
+cron = "0 3 * * *"
+
+[[zones]]
+name = "example.org"
+cron = "0 4 * * *"
+records = ["b", { name = "c", cron = "0 5 * * *" }]
+"#;
+
+    #[test]
+    fn migrates_every_cron() {
+        let mut document: toml_edit::DocumentMut = V1_CRONS.parse().unwrap();
+        let changes = migrate_document(&mut document).unwrap();
+        assert_eq!(
+            changes,
+            [
+                "service.cron \"*/5 * * * *\" -> \"0 */5 * * * *\"",
+                "defaults.cron \"0 * * * *\" -> \"0 0 * * * *\"",
+                "records[0].cron \"0 3 * * *\" -> \"0 0 3 * * *\"",
+                "zones[0].cron \"0 4 * * *\" -> \"0 0 4 * * *\"",
+                "zones[0].records[1].cron \"0 5 * * *\" -> \"0 0 5 * * *\"",
+            ]
+        );
+        assert_eq!(
+            document.to_string(),
+            r#"version = 2
+# Home network
+[service]
+cron = "0 */5 * * * *" # every five minutes
+
+[defaults]
+cron = "0 0 * * * *"
+
+[[records]]
+zone = "example.com"
+name = "a.example.com"
+cron = "0 0 3 * * *"
+
+[[zones]]
+name = "example.org"
+cron = "0 0 4 * * *"
+records = ["b", { name = "c", cron = "0 0 5 * * *" }]
+"#
+        );
+    }
+
+    #[test]
+    fn loads_v1_files() {
+        let dir = temp_dir("v1");
+        let path = dir.join("config.toml");
+        fs::write(
+            &path,
+            format!("[cloudflare]\napi_token = \"token\"\n\n{}", V1_CRONS),
+        )
+        .unwrap();
+
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.service.cron, "0 */5 * * * *");
+        let crons: Vec<_> = config
+            .records
+            .iter()
+            .map(|record| record.cron.as_deref().unwrap())
+            .collect();
+        assert_eq!(crons, ["0 0 3 * * *", "0 0 4 * * *", "0 0 5 * * *"]);
+    }
+
+    #[test]
+    fn leaves_current_files_alone() {
+        let current = "version = 2\n\n[service]\ncron = \"0 */5 * * * *\"\n";
+        let mut document: toml_edit::DocumentMut = current.parse().unwrap();
+        assert!(migrate_document(&mut document).unwrap().is_empty());
+        assert_eq!(document.to_string(), current);
+    }
+
+    #[test]
+    fn migrates_files_with_a_backup() {
+        let dir = temp_dir("migrate");
+        let path = dir.join("config.toml");
+        fs::write(&path, V1_CRONS).unwrap();
+
+        let (changes, backup) = migrate_file(&path).unwrap().unwrap();
+        assert_eq!(changes.len(), 5);
+        assert_eq!(backup, dir.join("config.toml.bak"));
+        assert_eq!(fs::read_to_string(&backup).unwrap(), V1_CRONS);
+        let migrated = fs::read_to_string(&path).unwrap();
+        assert!(migrated.starts_with("version = 2\n# Home network\n"));
+
+        // A second run finds nothing to do and keeps the backup
+        assert!(migrate_file(&path).unwrap().is_none());
+        assert_eq!(fs::read_to_string(&path).unwrap(), migrated);
+        assert_eq!(fs::read_to_string(&backup).unwrap(), V1_CRONS);
+    }
+
+    #[test]
+    fn parses_unsaved_content() {
+        let path = Path::new("/nonexistent/config.toml");
+        let content = "[cloudflare]\napi_token = \"token\"\n\n\
+                       [defaults]\nzone = \"example.com\"\n\n\
+                       [[records]]\nname = \"home.example.com\"\n";
+        let config = Config::parse(path, content).unwrap();
+        assert_eq!(config.records[0].zone, "example.com");
+
+        // [defaults] applies to every record, including ones added later
+        let content = content.replace("[defaults]", "[defaults]\nprovider = \"rfc2136\"");
+        let error = Config::parse(path, &content).unwrap_err();
+        assert!(
+            error.to_string().contains("no [rfc2136] section"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn qualifies_names() {
+        let cases = [
+            ("@", "example.com", "example.com"),
+            ("home", "example.com", "home.example.com"),
+            ("home", "example.com.", "home.example.com"),
+            ("vpn.home", "example.com", "vpn.home.example.com"),
+            ("home.example.com", "example.com", "home.example.com"),
+            ("Home.Example.COM", "example.com", "Home.Example.COM"),
+            ("home.example.com.", "example.com", "home.example.com"),
+            ("home.example.org.", "example.com", "home.example.org"),
+            ("example.com", "example.com", "example.com"),
+            ("myexample.com", "example.com", "myexample.com.example.com"),
+        ];
+        for (name, zone, expected) in cases {
+            assert_eq!(qualify_name(name, zone), expected, "{} in {}", name, zone);
+        }
+    }
+
+    /// Records a file expands to, as a TOML array
+    fn expanded(file: &str) -> Result<toml::Value> {
+        let mut root: toml::Table = toml::from_str(file).unwrap();
+        let defaults = take_defaults(&mut root)?;
+        expand_records(&mut root, &defaults)?;
+        Ok(root["records"].clone())
+    }
+
+    fn records(expected: &str) -> toml::Value {
+        toml::from_str::<toml::Table>(expected).unwrap()["records"].clone()
+    }
+
+    #[test]
+    fn expands_zones_and_defaults() {
+        let cases = [
+            (
+                "plain records take defaults they don't set",
+                r#"
+                [defaults]
+                zone = "example.com"
+                ttl = 300
+                [[records]]
+                name = "a.example.com"
+                [[records]]
+                name = "b.example.com"
+                ttl = 60
+                "#,
+                r#"records = [
+                    { name = "a.example.com", zone = "example.com", ttl = 300 },
+                    { name = "b.example.com", zone = "example.com", ttl = 60 },
+                ]"#,
+            ),
+            (
+                "zone keys apply to its records, overrides win",
+                r#"
+                [[zones]]
+                name = "example.com"
+                proxied = true
+                records = ["@", "home", { name = "vpn", proxied = false }]
+                "#,
+                r#"records = [
+                    { name = "example.com", zone = "example.com", proxied = true },
+                    { name = "home.example.com", zone = "example.com", proxied = true },
+                    { name = "vpn.example.com", zone = "example.com", proxied = false },
+                ]"#,
+            ),
+            (
+                "defaults fill what neither the zone nor the record sets",
+                r#"
+                [defaults]
+                ttl = 120
+                record_type = "AAAA"
+                [[zones]]
+                name = "example.org"
+                ttl = 600
+                records = ["home", { name = "www.example.org.", record_type = "A" }]
+                "#,
+                r#"records = [
+                    { name = "home.example.org", zone = "example.org", ttl = 600, record_type = "AAAA" },
+                    { name = "www.example.org", zone = "example.org", ttl = 600, record_type = "A" },
+                ]"#,
+            ),
+            (
+                "records come before zone groups",
+                r#"
+                [[zones]]
+                name = "example.org"
+                records = ["b"]
+                [[records]]
+                zone = "example.com"
+                name = "a.example.com"
+                "#,
+                r#"records = [
+                    { zone = "example.com", name = "a.example.com" },
+                    { name = "b.example.org", zone = "example.org" },
+                ]"#,
+            ),
+        ];
+        for (case, file, expected) in cases {
+            assert_eq!(expanded(file).unwrap(), records(expected), "{}", case);
+        }
+    }
+
+    #[test]
+    fn rejects_broken_zones() {
+        let cases = [
+            (
+                "[defaults]\nname = \"home\"",
+                "[defaults] cannot set a record name",
+            ),
+            (
+                "[[zones]]\nrecords = [\"home\"]",
+                "Every [[zones]] entry needs a name",
+            ),
+            (
+                "[[zones]]\nname = \"example.com\"",
+                "Zone example.com needs a records list",
+            ),
+            (
+                "[[zones]]\nname = \"example.com\"\nrecords = [{ ttl = 60 }]",
+                "Record in zone example.com is missing a name",
+            ),
+            (
+                "[[zones]]\nname = \"example.com\"\nrecords = [1]",
+                "Records in zone example.com must be names or inline tables",
+            ),
+        ];
+        for (file, message) in cases {
+            assert_eq!(expanded(file).unwrap_err().to_string(), message);
+        }
+    }
+
+    #[test]
+    fn merges_config_directories() {
+        let dir = temp_dir("conf-d");
+        fs::create_dir(dir.join("conf.d")).unwrap();
+        fs::write(
+            dir.join("config.toml"),
+            "[cloudflare]\napi_token = \"token\"\n\n[settings]\nconcurrency = 2\n\n\
+             [defaults]\nttl = 300\n\n\
+             [[records]]\nzone = \"example.com\"\nname = \"a.example.com\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("conf.d/20-zones.toml"),
+            "[[zones]]\nname = \"example.org\"\nrecords = [\"@\"]\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("conf.d/10-settings.toml"),
+            "[settings]\nconcurrency = 8\n\n\
+             [[records]]\nzone = \"example.com\"\nname = \"b.example.com\"\nttl = 60\n",
+        )
+        .unwrap();
+        fs::write(dir.join("conf.d/notes.txt"), "not a config file").unwrap();
+
+        let config = Config::load(&dir).unwrap();
+        assert_eq!(config.settings.concurrency, 8);
+        let records: Vec<_> = config
+            .records
+            .iter()
+            .map(|record| (record.name.as_str(), record.ttl))
+            .collect();
+        assert_eq!(
+            records,
+            [
+                ("a.example.com", 300),
+                ("b.example.com", 60),
+                ("example.org", 300),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_conflicting_fragments() {
+        let base = Path::new("config.toml");
+        let (first, second) = (Path::new("conf.d/a.toml"), Path::new("conf.d/b.toml"));
+        let record = "[[records]]\nzone = \"example.com\"\nname = \"a.example.com\"\n";
+        let cases = [
+            (
+                "[settings]\nconcurrency = 2",
+                "[settings]\nconcurrency = 3",
+                "settings.concurrency is set differently in conf.d/a.toml and conf.d/b.toml",
+            ),
+            (
+                "[defaults]\nttl = 60",
+                "[defaults]\nttl = 120",
+                "defaults.ttl is set differently in conf.d/a.toml and conf.d/b.toml",
+            ),
+            (
+                record,
+                record,
+                "Duplicate A record a.example.com (cloudflare) in conf.d/a.toml and conf.d/b.toml",
+            ),
+        ];
+        for (a, b, message) in cases {
+            let texts = [
+                (base, String::new()),
+                (first, a.to_string()),
+                (second, b.to_string()),
+            ];
+            assert_eq!(merge_texts(&texts).unwrap_err().to_string(), message);
+        }
+
+        // The base file only provides values for fragments to override
+        let texts = [
+            (base, "[settings]\nconcurrency = 2".to_string()),
+            (first, "[settings]\nconcurrency = 3".to_string()),
+        ];
+        let merged = merge_texts(&texts).unwrap();
+        assert_eq!(merged["settings"]["concurrency"].as_integer(), Some(3));
     }
 }
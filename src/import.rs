@@ -0,0 +1,206 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use tracing::info;
+
+use crate::cloudflare::{user_comment, DdnsClient, DnsRecord};
+use crate::config::{write_atomic, Config, RecordType};
+
+/// Append the zone's A/AAAA records to the config as `[[records]]` entries
+///
+/// Records already in the config are skipped. With `dry_run` the entries are
+/// printed instead of written.
+pub async fn run(path: &Path, zone: &str, filter: Option<&str>, dry_run: bool) -> Result<()> {
+    if !path.is_file() {
+        anyhow::bail!(
+            "{} is not a config file; create one with `cddns init` or add a [cloudflare] section first",
+            path.display()
+        );
+    }
+    let config = Config::read(path)?;
+    if config.cloudflare.api_token.is_empty() {
+        anyhow::bail!(
+            "No Cloudflare API token in {}; set api_token, api_token_file or CF_API_TOKEN",
+            path.display()
+        );
+    }
+
+    let client = DdnsClient::new(&config.cloudflare.api_token)?;
+    let zone_id = client.get_zone_id(zone).await?;
+    let records = client.list_records(&zone_id).await?;
+
+    let mut imported = toml_edit::ArrayOfTables::new();
+    let mut skipped = 0;
+    for record in &records {
+        let Some(record_type) = address_type(record) else {
+            continue;
+        };
+        if filter.is_some_and(|pattern| !wildcard_match(pattern, &record.name)) {
+            continue;
+        }
+        if config
+            .records
+            .iter()
+            .any(|r| r.record_type == record_type && r.name.eq_ignore_ascii_case(&record.name))
+        {
+            skipped += 1;
+            continue;
+        }
+        imported.push(record_table(zone, record, record_type));
+    }
+
+    if skipped > 0 {
+        info!("Skipped {} record(s) already in the config", skipped);
+    }
+    if imported.is_empty() {
+        info!("No new A/AAAA records to import from {}", zone);
+        return Ok(());
+    }
+    let count = imported.len();
+
+    if dry_run {
+        let mut preview = toml_edit::DocumentMut::new();
+        preview.insert("records", toml_edit::Item::ArrayOfTables(imported));
+        print!("{}", preview);
+        info!(
+            "[DRY-RUN] Would import {} record(s) into {}",
+            count,
+            path.display()
+        );
+        return Ok(());
+    }
+
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+    let mut document: toml_edit::DocumentMut = content
+        .parse()
+        .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+
+    match document.get_mut("records") {
+        Some(toml_edit::Item::ArrayOfTables(records)) => {
+            for table in imported.iter() {
+                records.push(table.clone());
+            }
+        }
+        Some(_) => anyhow::bail!(
+            "records in {} is not an array of tables ([[records]])",
+            path.display()
+        ),
+        None => {
+            document.insert("records", toml_edit::Item::ArrayOfTables(imported));
+        }
+    }
+
+    // [defaults] and the other rules apply to the new entries too; check the
+    // result before it replaces the file
+    let merged = document.to_string();
+    Config::parse(path, &merged).with_context(|| {
+        format!(
+            "Imported records would make {} invalid; left it unchanged",
+            path.display()
+        )
+    })?;
+    write_atomic(path, &merged)?;
+    info!("Imported {} record(s) into {}", count, path.display());
+    Ok(())
+}
+
+/// Record type of an A or AAAA record
+fn address_type(record: &DnsRecord) -> Option<RecordType> {
+    match record.record_type.as_str() {
+        "A" => Some(RecordType::A),
+        "AAAA" => Some(RecordType::AAAA),
+        _ => None,
+    }
+}
+
+/// Config entry keeping the record's proxy status, TTL, comment and tags
+fn record_table(zone: &str, record: &DnsRecord, record_type: RecordType) -> toml_edit::Table {
+    let mut table = toml_edit::Table::new();
+    table.insert("zone", toml_edit::value(zone));
+    table.insert("name", toml_edit::value(&record.name));
+    table.insert("record_type", toml_edit::value(record_type.to_string()));
+    table.insert("proxied", toml_edit::value(record.proxied));
+    table.insert("ttl", toml_edit::value(i64::from(record.ttl)));
+    if let Some(comment) = user_comment(record) {
+        table.insert("comment", toml_edit::value(comment));
+    }
+    if !record.tags.is_empty() {
+        let tags: toml_edit::Array = record.tags.iter().collect();
+        table.insert("tags", toml_edit::value(tags));
+    }
+    table
+}
+
+/// Case-insensitive match with `*` (any run of characters) and `?` (one character)
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    // Position after the last `*` and the name position it is matched up to
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    p = star_p;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_wildcards() {
+        let cases = [
+            ("*.example.com", "home.example.com", true),
+            ("*.example.com", "example.com", false),
+            ("home*", "home.example.com", true),
+            ("home*", "office.example.com", false),
+            ("h*e.example.com", "home.example.com", true),
+            ("h*e.example.com", "host.example.com", false),
+            ("*ome*", "home.example.com", true),
+            ("h?me.example.com", "home.example.com", true),
+            ("h?me.example.com", "hoome.example.com", false),
+            ("*", "anything.example.com", true),
+        ];
+        for (pattern, name, expected) in cases {
+            assert_eq!(
+                wildcard_match(pattern, name),
+                expected,
+                "{} ~ {}",
+                pattern,
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn matches_exact_names() {
+        assert!(wildcard_match("home.example.com", "home.example.com"));
+        assert!(!wildcard_match("home.example.com", "home.example.co"));
+        assert!(!wildcard_match("home.example.co", "home.example.com"));
+    }
+
+    #[test]
+    fn ignores_case() {
+        assert!(wildcard_match("HOME.*", "home.Example.com"));
+        assert!(wildcard_match("home.example.com", "Home.EXAMPLE.com"));
+    }
+}
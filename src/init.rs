@@ -0,0 +1,282 @@
+use anyhow::{Context, Result};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use crate::cloudflare::DdnsClient;
+use crate::config::{
+    default_ipv4_url, default_ipv6_url, qualify_name, Config, RecordConfig, RecordType,
+};
+use crate::ip::get_public_ip;
+
+/// Record names suggested for each zone
+const DEFAULT_NAME: &str = "home";
+
+/// Walk through creating a config file
+pub async fn run(path: &Path, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        anyhow::bail!(
+            "{} already exists; use --force to replace it (a .bak copy is kept)",
+            path.display()
+        );
+    }
+
+    println!("This wizard creates {}.", path.display());
+    println!("Create an API token with Zone:Read and DNS:Edit permissions at");
+    println!("https://dash.cloudflare.com/profile/api-tokens\n");
+
+    // Token, falling back to the environment so it can stay out of the file
+    let env_token = std::env::var("CF_API_TOKEN").ok().filter(|t| !t.is_empty());
+    let question = if env_token.is_some() {
+        "API token (leave empty to use CF_API_TOKEN)"
+    } else {
+        "API token"
+    };
+    let entered = prompt_secret(question)?;
+    let (token, from_env) = match (entered.is_empty(), env_token) {
+        (false, _) => (entered, false),
+        (true, Some(env_token)) => (env_token, true),
+        (true, None) => anyhow::bail!("An API token is required"),
+    };
+
+    let client = DdnsClient::new(&token)?;
+    client.verify_token().await?;
+    println!("Token is valid.\n");
+
+    // Zones
+    let zones = client.list_zones().await?;
+    if zones.is_empty() {
+        anyhow::bail!("The token can't access any zones; check its Zone:Read permission");
+    }
+    let selected = select_zones(&zones)?;
+
+    // Record names per zone
+    let mut names = Vec::new();
+    for zone in &selected {
+        let answer = prompt(
+            &format!("Record names in {} (comma-separated, @ for the apex)", zone),
+            DEFAULT_NAME,
+        )?;
+        for name in split_list(&answer) {
+            names.push((zone.clone(), qualify_name(name, zone)));
+        }
+    }
+    if names.is_empty() {
+        anyhow::bail!("No records entered");
+    }
+
+    // Address families, from what this machine can reach
+    let ipv4 = get_public_ip(RecordType::A, &default_ipv4_url(), &default_ipv6_url()).await;
+    let ipv6 = get_public_ip(RecordType::AAAA, &default_ipv4_url(), &default_ipv6_url()).await;
+    let mut record_types = Vec::new();
+    match &ipv4 {
+        Ok(ip) => {
+            println!("Detected public IPv4: {}", ip);
+            record_types.push(RecordType::A);
+        }
+        Err(e) => println!("No public IPv4 detected ({})", e),
+    }
+    if let Ok(ip) = &ipv6 {
+        println!("Detected public IPv6: {}", ip);
+        if record_types.is_empty() || confirm("Also create AAAA records?", false)? {
+            record_types.push(RecordType::AAAA);
+        }
+    }
+    if record_types.is_empty() {
+        println!("Creating A records; they will update once an IPv4 is reachable.");
+        record_types.push(RecordType::A);
+    }
+
+    let proxied = confirm("Proxy the records through Cloudflare?", false)?;
+
+    // Build and validate the config before touching the file
+    let (first_zone, first_name) = names[0].clone();
+    let mut config = Config::from_args(
+        token,
+        first_zone,
+        first_name,
+        record_types[0],
+        proxied,
+        1,
+        None,
+    )?;
+    let template = config.records[0].clone();
+    config.records = names
+        .iter()
+        .flat_map(|(zone, name)| {
+            record_types.iter().map(|record_type| RecordConfig {
+                zone: zone.clone(),
+                name: name.clone(),
+                record_type: *record_type,
+                ..template.clone()
+            })
+        })
+        .collect();
+    if from_env {
+        // Written as empty so CF_API_TOKEN keeps supplying it
+        config.keep_written_token(String::new());
+    }
+    config.validate()?;
+
+    // Write the new config aside first so a failed write leaves the old one in place
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    let _ = std::fs::remove_file(&temp);
+    write_private(&temp, &config.file_content(&temp)?)?;
+
+    let mut backup = None;
+    if path.exists() {
+        let mut moved = path.as_os_str().to_owned();
+        moved.push(".bak");
+        if let Err(e) = std::fs::rename(path, &moved) {
+            let _ = std::fs::remove_file(&temp);
+            return Err(e).with_context(|| format!("Failed to back up {}", path.display()));
+        }
+        backup = Some(PathBuf::from(moved));
+    }
+    if let Err(e) = std::fs::rename(&temp, path) {
+        if let Some(backup) = &backup {
+            let _ = std::fs::rename(backup, path);
+        }
+        let _ = std::fs::remove_file(&temp);
+        return Err(e).with_context(|| format!("Failed to write config file: {}", path.display()));
+    }
+    if let Some(backup) = backup {
+        println!("Moved the old config to {}", backup.display());
+    }
+
+    println!(
+        "\nWrote {} with {} record(s).",
+        path.display(),
+        config.records.len()
+    );
+    println!(
+        "Check it with:  cddns config -f {} --dry-run",
+        path.display()
+    );
+    Ok(())
+}
+
+/// Let the user pick zones by number
+fn select_zones(zones: &[String]) -> Result<Vec<String>> {
+    if let [zone] = zones {
+        println!("Using zone {}", zone);
+        return Ok(vec![zone.clone()]);
+    }
+
+    println!("Zones this token can access:");
+    for (i, zone) in zones.iter().enumerate() {
+        println!("  {:>2}) {}", i + 1, zone);
+    }
+
+    loop {
+        let answer = prompt("Zones to manage (numbers, comma-separated)", "1")?;
+        let picked: Option<Vec<String>> = split_list(&answer)
+            .map(|n| {
+                n.parse::<usize>()
+                    .ok()
+                    .and_then(|n| n.checked_sub(1))
+                    .and_then(|i| zones.get(i))
+                    .cloned()
+            })
+            .collect();
+        match picked {
+            Some(picked) if !picked.is_empty() => return Ok(picked),
+            _ => println!("Enter numbers between 1 and {}", zones.len()),
+        }
+    }
+}
+
+fn split_list(answer: &str) -> impl Iterator<Item = &str> {
+    answer.split(',').map(str::trim).filter(|s| !s.is_empty())
+}
+
+/// Ask a question, returning the default for an empty answer
+fn prompt(question: &str, default: &str) -> Result<String> {
+    print!("{} [{}]: ", question, default);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer)? == 0 {
+        anyhow::bail!("Input closed");
+    }
+    let answer = answer.trim();
+    Ok(if answer.is_empty() { default } else { answer }.to_string())
+}
+
+/// Ask a yes/no question
+fn confirm(question: &str, default: bool) -> Result<bool> {
+    let answer = prompt(question, if default { "Y/n" } else { "y/N" })?;
+    Ok(match answer.to_lowercase().as_str() {
+        "y" | "yes" => true,
+        "n" | "no" => false,
+        _ => default,
+    })
+}
+
+/// Ask for a secret without echoing it
+fn prompt_secret(question: &str) -> Result<String> {
+    print!("{}: ", question);
+    io::stdout().flush()?;
+
+    if !io::stdin().is_terminal() {
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer)?;
+        return Ok(answer.trim().to_string());
+    }
+
+    terminal::enable_raw_mode().context("Failed to enable raw mode")?;
+    let answer = read_hidden();
+    terminal::disable_raw_mode().context("Failed to disable raw mode")?;
+    println!();
+    answer
+}
+
+fn read_hidden() -> Result<String> {
+    let mut answer = String::new();
+    loop {
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Enter => return Ok(answer.trim().to_string()),
+            KeyCode::Backspace => {
+                answer.pop();
+            }
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                anyhow::bail!("Cancelled")
+            }
+            KeyCode::Char(c) => answer.push(c),
+            _ => {}
+        }
+    }
+}
+
+/// Write the config readable by the owner only, since it holds the token
+///
+/// The file is created with these permissions, so the token is never readable
+/// by others, not even briefly.
+#[cfg(unix)]
+fn write_private(path: &Path, content: &str) -> Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .with_context(|| format!("Failed to write config file: {}", path.display()))
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, content: &str) -> Result<()> {
+    std::fs::write(path, content)
+        .with_context(|| format!("Failed to write config file: {}", path.display()))
+}
@@ -11,14 +11,66 @@ pub async fn get_public_ip(
     ipv6_url: &str,
 ) -> Result<IpAddr> {
     match record_type {
-        RecordType::A => {
-            let ip = get_public_ipv4(ipv4_url).await?;
-            Ok(IpAddr::V4(ip))
-        }
         RecordType::AAAA => {
             let ip = get_public_ipv6(ipv6_url).await?;
             Ok(IpAddr::V6(ip))
         }
+        // Non-address records pick their family via RecordConfig::ip_record_type
+        _ => {
+            let ip = get_public_ipv4(ipv4_url).await?;
+            Ok(IpAddr::V4(ip))
+        }
+    }
+}
+
+/// Public addresses detected once for all records of an update
+#[derive(Default)]
+pub struct PublicIps {
+    ipv4: Option<Result<IpAddr, String>>,
+    ipv6: Option<Result<IpAddr, String>>,
+}
+
+impl PublicIps {
+    /// Detect each address family the given record types need, both at once
+    pub async fn detect(
+        record_types: impl IntoIterator<Item = RecordType>,
+        ipv4_url: &str,
+        ipv6_url: &str,
+    ) -> Self {
+        let (mut ipv4, mut ipv6) = (false, false);
+        for record_type in record_types {
+            match record_type {
+                RecordType::AAAA => ipv6 = true,
+                _ => ipv4 = true,
+            }
+        }
+
+        let detect = |needed: bool, record_type: RecordType| async move {
+            if !needed {
+                return None;
+            }
+            Some(
+                get_public_ip(record_type, ipv4_url, ipv6_url)
+                    .await
+                    .map_err(|e| e.to_string()),
+            )
+        };
+        let (ipv4, ipv6) =
+            tokio::join!(detect(ipv4, RecordType::A), detect(ipv6, RecordType::AAAA));
+        Self { ipv4, ipv6 }
+    }
+
+    /// Detected address for a record type
+    pub fn get(&self, record_type: RecordType) -> Result<IpAddr> {
+        let (detected, family) = match record_type {
+            RecordType::AAAA => (&self.ipv6, "IPv6"),
+            _ => (&self.ipv4, "IPv4"),
+        };
+        match detected {
+            Some(Ok(ip)) => Ok(*ip),
+            Some(Err(e)) => anyhow::bail!("{}", e),
+            None => anyhow::bail!("Public {} was not detected", family),
+        }
     }
 }
 
@@ -0,0 +1,395 @@
+use anyhow::{Context, Result};
+use cloudflare::framework::endpoint::spec::EndpointSpec;
+use cloudflare::framework::endpoint::{serialize_query, Method, RequestBody};
+use cloudflare::framework::response::{ApiResult, ApiSuccess};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv6Addr};
+use std::time::Duration;
+use tracing::{debug, info};
+
+use crate::cloudflare::{api_client, ApiClient};
+use crate::config::IpListConfig;
+use crate::provider::UpdateResult;
+
+/// Page size for list item requests
+const ITEMS_PER_PAGE: u32 = 500;
+
+/// How often to poll a bulk operation
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How many times to poll a bulk operation before giving up
+const POLL_ATTEMPTS: u32 = 60;
+
+/// Client keeping entries of Cloudflare IP Lists at the dynamic IP
+pub struct IpListClient {
+    client: ApiClient,
+}
+
+impl IpListClient {
+    /// Create a new IP List client with the given API token
+    pub fn new(api_token: &str) -> Result<Self> {
+        Ok(Self {
+            client: api_client(api_token)?,
+        })
+    }
+
+    /// Replace our entry in the list if it doesn't hold the given IP
+    pub async fn update_ip_list(&self, list: &IpListConfig, ip: IpAddr) -> Result<UpdateResult> {
+        let account_id = list.account_id.as_str();
+        let list_id = self.get_list_id(account_id, &list.list).await?;
+
+        let desired = list_entry(ip);
+        let ours: Vec<ListItem> = self
+            .list_items(account_id, &list_id)
+            .await?
+            .into_iter()
+            .filter(|item| item.comment.as_deref() == Some(list.comment.as_str()))
+            .collect();
+
+        let (current, stale): (Vec<_>, Vec<_>) = ours
+            .into_iter()
+            .partition(|item| item.ip.as_deref().is_some_and(|e| same_entry(e, &desired)));
+
+        if !current.is_empty() && stale.is_empty() {
+            debug!("IP list {} already contains {}", list.list, desired);
+            return Ok(UpdateResult::Unchanged);
+        }
+
+        // Add the new entry before removing the old one so the allowlist never goes empty
+        if current.is_empty() {
+            let operation = self
+                .client
+                .request(&CreateListItems {
+                    account_identifier: account_id,
+                    list_identifier: &list_id,
+                    items: vec![NewListItem {
+                        ip: &desired,
+                        comment: &list.comment,
+                    }],
+                })
+                .await
+                .context("Failed to add IP list item")?;
+            self.wait_for(account_id, &operation.result.operation_id)
+                .await?;
+            info!("Added {} to IP list {}", desired, list.list);
+        }
+
+        let old_ip = stale
+            .first()
+            .and_then(|item| item.ip.as_deref())
+            .and_then(entry_ip);
+
+        if !stale.is_empty() {
+            let operation = self
+                .client
+                .request(&DeleteListItems {
+                    account_identifier: account_id,
+                    list_identifier: &list_id,
+                    items: stale.iter().map(|item| ItemId { id: &item.id }).collect(),
+                })
+                .await
+                .context("Failed to remove IP list item")?;
+            self.wait_for(account_id, &operation.result.operation_id)
+                .await?;
+            debug!(
+                "Removed {} stale item(s) from IP list {}",
+                stale.len(),
+                list.list
+            );
+        }
+
+        if stale.is_empty() {
+            Ok(UpdateResult::Created)
+        } else {
+            Ok(UpdateResult::Updated { old_ip, new_ip: ip })
+        }
+    }
+
+    /// Look up a list ID by name
+    async fn get_list_id(&self, account_id: &str, name: &str) -> Result<String> {
+        let response = self
+            .client
+            .request(&ListLists {
+                account_identifier: account_id,
+            })
+            .await
+            .context("Failed to list IP lists")?;
+
+        let list = response
+            .result
+            .0
+            .into_iter()
+            .find(|l| l.name == name)
+            .with_context(|| format!("IP list not found: {}", name))?;
+
+        if list.kind != "ip" {
+            anyhow::bail!("List {} is a {} list, not an IP list", name, list.kind);
+        }
+
+        debug!("Found list ID: {} for {}", list.id, name);
+        Ok(list.id)
+    }
+
+    /// Fetch every item of a list
+    async fn list_items(&self, account_id: &str, list_id: &str) -> Result<Vec<ListItem>> {
+        let mut items = Vec::new();
+        let mut cursor = None;
+
+        loop {
+            let response = self
+                .client
+                .request(&ListListItems {
+                    account_identifier: account_id,
+                    list_identifier: list_id,
+                    params: ListItemsParams {
+                        cursor,
+                        per_page: ITEMS_PER_PAGE,
+                    },
+                })
+                .await
+                .context("Failed to list IP list items")?;
+
+            items.extend(response.result.0);
+            cursor = response
+                .result_info
+                .as_ref()
+                .and_then(|info| info.pointer("/cursors/after"))
+                .and_then(|after| after.as_str())
+                .map(str::to_string);
+            if cursor.is_none() {
+                break;
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// Wait for an asynchronous bulk operation to finish
+    async fn wait_for(&self, account_id: &str, operation_id: &str) -> Result<()> {
+        for _ in 0..POLL_ATTEMPTS {
+            let response = self
+                .client
+                .request(&GetBulkOperation {
+                    account_identifier: account_id,
+                    operation_identifier: operation_id,
+                })
+                .await
+                .context("Failed to check IP list operation")?;
+
+            match response.result.status.as_str() {
+                "completed" => return Ok(()),
+                "failed" => anyhow::bail!(
+                    "IP list operation failed: {}",
+                    response.result.error.unwrap_or_default()
+                ),
+                _ => tokio::time::sleep(POLL_INTERVAL).await,
+            }
+        }
+
+        anyhow::bail!("Timed out waiting for IP list operation {}", operation_id)
+    }
+}
+
+/// List entry for an IP; IPv6 entries are stored as the /64 they belong to
+fn list_entry(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(v4) => v4.to_string(),
+        IpAddr::V6(v6) => {
+            let prefix = u128::from(v6) & (u128::MAX << 64);
+            format!("{}/64", Ipv6Addr::from(prefix))
+        }
+    }
+}
+
+/// Address part of a list entry
+fn entry_ip(entry: &str) -> Option<IpAddr> {
+    entry.split('/').next()?.parse().ok()
+}
+
+/// Compare list entries, ignoring address formatting
+fn same_entry(current: &str, desired: &str) -> bool {
+    let prefix = |entry: &str| entry.split_once('/').map(|(_, len)| len.to_string());
+    entry_ip(current).is_some()
+        && entry_ip(current) == entry_ip(desired)
+        && prefix(current) == prefix(desired)
+}
+
+/// Account-level list, as returned by the Cloudflare API
+#[derive(Deserialize, Debug)]
+struct List {
+    id: String,
+    name: String,
+    kind: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(transparent)]
+struct Lists(Vec<List>);
+
+/// Item of an IP list
+#[derive(Deserialize, Debug)]
+struct ListItem {
+    id: String,
+    #[serde(default)]
+    ip: Option<String>,
+    #[serde(default)]
+    comment: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(transparent)]
+struct ListItems(Vec<ListItem>);
+
+/// Handle of an asynchronous list modification
+#[derive(Deserialize, Debug)]
+struct BulkOperationId {
+    operation_id: String,
+}
+
+/// Progress of an asynchronous list modification
+#[derive(Deserialize, Debug)]
+struct BulkOperation {
+    status: String,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+impl ApiResult for Lists {}
+impl ApiResult for ListItems {}
+impl ApiResult for BulkOperationId {}
+impl ApiResult for BulkOperation {}
+
+/// List the account's lists
+struct ListLists<'a> {
+    account_identifier: &'a str,
+}
+
+impl EndpointSpec for ListLists<'_> {
+    type JsonResponse = Lists;
+    type ResponseType = ApiSuccess<Self::JsonResponse>;
+
+    fn method(&self) -> Method {
+        Method::GET
+    }
+    fn path(&self) -> String {
+        format!("accounts/{}/rules/lists", self.account_identifier)
+    }
+}
+
+/// List the items of a list
+struct ListListItems<'a> {
+    account_identifier: &'a str,
+    list_identifier: &'a str,
+    params: ListItemsParams,
+}
+
+#[derive(Serialize, Debug)]
+struct ListItemsParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cursor: Option<String>,
+    per_page: u32,
+}
+
+impl EndpointSpec for ListListItems<'_> {
+    type JsonResponse = ListItems;
+    type ResponseType = ApiSuccess<Self::JsonResponse>;
+
+    fn method(&self) -> Method {
+        Method::GET
+    }
+    fn path(&self) -> String {
+        format!(
+            "accounts/{}/rules/lists/{}/items",
+            self.account_identifier, self.list_identifier
+        )
+    }
+    fn query(&self) -> Option<String> {
+        serialize_query(&self.params)
+    }
+}
+
+/// Append items to a list
+struct CreateListItems<'a> {
+    account_identifier: &'a str,
+    list_identifier: &'a str,
+    items: Vec<NewListItem<'a>>,
+}
+
+#[derive(Serialize, Debug)]
+struct NewListItem<'a> {
+    ip: &'a str,
+    comment: &'a str,
+}
+
+impl EndpointSpec for CreateListItems<'_> {
+    type JsonResponse = BulkOperationId;
+    type ResponseType = ApiSuccess<Self::JsonResponse>;
+
+    fn method(&self) -> Method {
+        Method::POST
+    }
+    fn path(&self) -> String {
+        format!(
+            "accounts/{}/rules/lists/{}/items",
+            self.account_identifier, self.list_identifier
+        )
+    }
+    fn body(&self) -> Option<RequestBody<'_>> {
+        serde_json::to_string(&self.items)
+            .ok()
+            .map(RequestBody::Json)
+    }
+}
+
+/// Remove items from a list
+struct DeleteListItems<'a> {
+    account_identifier: &'a str,
+    list_identifier: &'a str,
+    items: Vec<ItemId<'a>>,
+}
+
+#[derive(Serialize, Debug)]
+struct ItemId<'a> {
+    id: &'a str,
+}
+
+impl EndpointSpec for DeleteListItems<'_> {
+    type JsonResponse = BulkOperationId;
+    type ResponseType = ApiSuccess<Self::JsonResponse>;
+
+    fn method(&self) -> Method {
+        Method::DELETE
+    }
+    fn path(&self) -> String {
+        format!(
+            "accounts/{}/rules/lists/{}/items",
+            self.account_identifier, self.list_identifier
+        )
+    }
+    fn body(&self) -> Option<RequestBody<'_>> {
+        serde_json::to_string(&serde_json::json!({ "items": self.items }))
+            .ok()
+            .map(RequestBody::Json)
+    }
+}
+
+/// Check on an asynchronous list modification
+struct GetBulkOperation<'a> {
+    account_identifier: &'a str,
+    operation_identifier: &'a str,
+}
+
+impl EndpointSpec for GetBulkOperation<'_> {
+    type JsonResponse = BulkOperation;
+    type ResponseType = ApiSuccess<Self::JsonResponse>;
+
+    fn method(&self) -> Method {
+        Method::GET
+    }
+    fn path(&self) -> String {
+        format!(
+            "accounts/{}/rules/lists/bulk_operations/{}",
+            self.account_identifier, self.operation_identifier
+        )
+    }
+}
@@ -23,6 +23,8 @@ pub enum Command {
     Stop,
     /// Ping to check if service is alive
     Ping,
+    /// Get the next scheduled runs
+    GetSchedule { count: usize },
 }
 
 /// Responses from the service
@@ -34,6 +36,8 @@ pub enum Response {
     UpdateTriggered,
     /// Update completed with result
     UpdateResult { success: bool, message: String },
+    /// An update was already running; this one was queued behind it
+    UpdateQueued { message: String },
     /// Service is stopping
     Stopping,
     /// Pong response
@@ -42,6 +46,8 @@ pub enum Response {
     Error(String),
     /// Log message from service
     Log(LogMessage),
+    /// Upcoming scheduled runs (ISO 8601)
+    Schedule(Vec<String>),
 }
 
 /// Current service status
@@ -49,12 +55,21 @@ pub enum Response {
 pub struct ServiceStatus {
     /// Whether the service is running
     pub running: bool,
-    /// Current cron schedule
+    /// Current schedule (cron expression or interval)
     pub cron: String,
     /// Last update time (ISO 8601)
     pub last_update: Option<String>,
     /// Last update result
     pub last_result: Option<String>,
+    /// What started the last update cycle (e.g., "schedule every 5m", "network change")
+    ///
+    /// Fields added after the first release default when missing, so a TUI and
+    /// a service from different releases still understand each other.
+    #[serde(default)]
+    pub last_trigger: Option<String>,
+    /// Update cycles that failed in a row (failed cycles are retried with backoff)
+    #[serde(default)]
+    pub consecutive_failures: u32,
     /// Current detected IP
     pub current_ip: Option<String>,
     /// Number of records configured
@@ -185,3 +200,17 @@ pub async fn send_command(cmd: Command) -> Result<Response> {
     conn.send_command(&cmd).await?;
     conn.receive_response().await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_status_from_older_services() {
+        let status = r#"{"running":true,"cron":"0 */5 * * * *","last_update":null,"last_result":"Success","current_ip":"198.51.100.7","record_count":2,"next_run":null}"#;
+        let status: ServiceStatus = serde_json::from_str(status).unwrap();
+        assert_eq!(status.last_trigger, None);
+        assert_eq!(status.consecutive_failures, 0);
+        assert_eq!(status.record_count, 2);
+    }
+}
@@ -1,20 +1,36 @@
 mod cloudflare;
 mod config;
+mod dyndns;
+mod import;
+mod init;
 mod ip;
+mod ip_list;
 mod ipc;
+#[cfg(target_os = "linux")]
+mod netlink;
+mod origin;
+mod provider;
+mod prune;
+mod rfc2136;
+mod rules;
 mod service;
 mod tui;
+mod updater;
+mod validate;
+mod webhook;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 use std::net::IpAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tracing::{error, info, warn, Level};
 use tracing_subscriber::EnvFilter;
 
-use crate::cloudflare::{DdnsClient, UpdateResult};
-use crate::config::{Config, RecordType};
-use crate::ip::get_public_ip;
+use crate::cloudflare::DdnsClient;
+use crate::config::{Config, Provider, RecordType};
+use crate::ip::PublicIps;
+use crate::provider::{Providers, UpdateResult};
+use crate::webhook::SentRequests;
 
 /// Cloudflare DDNS Updater
 #[derive(Parser, Debug)]
@@ -32,8 +48,12 @@ struct Cli {
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Update DNS record using a config file
+    #[command(args_conflicts_with_subcommands = true)]
     Config {
-        /// Path to the configuration file
+        #[command(subcommand)]
+        action: Option<ConfigAction>,
+
+        /// Path to the configuration file or directory (config.toml + conf.d/*.toml)
         #[arg(short, long, default_value = "config.toml")]
         file: PathBuf,
 
@@ -77,6 +97,36 @@ enum Commands {
         dry_run: bool,
     },
 
+    /// Create a config file step by step
+    Init {
+        /// Path of the configuration file to write
+        #[arg(short, long, default_value = "config.toml")]
+        config: PathBuf,
+
+        /// Replace an existing file (the old one is kept as .bak)
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Add existing Cloudflare A/AAAA records to the config
+    Import {
+        /// Zone to import records from (e.g., "example.com")
+        #[arg(short, long)]
+        zone: String,
+
+        /// Only import records whose name matches this pattern (e.g., "home*")
+        #[arg(long)]
+        filter: Option<String>,
+
+        /// Path to the configuration file
+        #[arg(short, long, default_value = "config.toml")]
+        config: PathBuf,
+
+        /// Print the entries instead of writing them
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+
     /// Open the interactive TUI
     Ui {
         /// Optional: Load config file into TUI
@@ -84,14 +134,49 @@ enum Commands {
         config: Option<PathBuf>,
     },
 
+    /// Check a config file or directory and report every problem with its location
+    Validate {
+        /// Path to the configuration file or directory
+        #[arg(short, long, default_value = "config.toml")]
+        config: PathBuf,
+    },
+
+    /// Delete records cddns created that are no longer in the config
+    Prune {
+        /// Path to the configuration file
+        #[arg(short, long, default_value = "config.toml")]
+        config: PathBuf,
+
+        /// Actually delete the records (default is to only list them)
+        #[arg(long)]
+        apply: bool,
+    },
+
     /// Run as a background service with cron scheduling
     Service {
-        /// Path to the configuration file
+        /// Path to the configuration file or directory (config.toml + conf.d/*.toml)
         #[arg(short, long, default_value = "config.toml")]
         config: PathBuf,
     },
 }
 
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    /// Upgrade a config file (or directory) to the current layout, keeping a backup
+    Migrate {
+        /// Path to the configuration file or directory
+        #[arg(short, long, default_value = "config.toml")]
+        file: PathBuf,
+    },
+
+    /// Print a JSON Schema for config files (for Taplo, even-better-toml, CI)
+    Schema {
+        /// Write the schema to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
@@ -114,7 +199,15 @@ async fn main() -> Result<()> {
     }
 
     match cli.command {
-        Some(Commands::Config { file, dry_run }) => run_with_config(&file, dry_run).await,
+        Some(Commands::Config {
+            action: Some(ConfigAction::Migrate { file }),
+            ..
+        }) => migrate_config(&file),
+        Some(Commands::Config {
+            action: Some(ConfigAction::Schema { output }),
+            ..
+        }) => write_schema(output.as_deref()),
+        Some(Commands::Config { file, dry_run, .. }) => run_with_config(&file, dry_run).await,
         Some(Commands::Update {
             api_token,
             zone,
@@ -137,10 +230,19 @@ async fn main() -> Result<()> {
             )
             .await
         }
+        Some(Commands::Init { config, force }) => init::run(&config, force).await,
+        Some(Commands::Import {
+            zone,
+            filter,
+            config,
+            dry_run,
+        }) => import::run(&config, &zone, filter.as_deref(), dry_run).await,
         Some(Commands::Ui { config }) => {
             // Pass the config path to TUI - it will handle loading/saving
             tui::run(config).await
         }
+        Some(Commands::Validate { config }) => validate::run(&config),
+        Some(Commands::Prune { config, apply }) => run_prune(&config, apply).await,
         Some(Commands::Service { config }) => service::run(config).await,
         None => {
             // Default behavior: try to load config.toml
@@ -148,6 +250,7 @@ async fn main() -> Result<()> {
                 run_with_config(&PathBuf::from("config.toml"), false).await
             } else {
                 eprintln!("No config file found. Use one of the following:");
+                eprintln!("  cddns init  - Create a config file step by step");
                 eprintln!("  cddns config -f <config.toml>  - Use a config file");
                 eprintln!("  cddns update -t <token> -z <zone> -r <record>  - Use CLI arguments");
                 eprintln!("  cddns ui  - Open interactive TUI");
@@ -169,7 +272,75 @@ async fn run_with_config(path: &PathBuf, dry_run: bool) -> Result<()> {
         warn!("Running in dry-run mode - no changes will be made");
     }
 
-    run_update(&config, dry_run).await
+    run_update(&config, dry_run).await?;
+
+    if config.settings.prune_orphans {
+        prune_orphans(&config, dry_run).await?;
+    }
+
+    Ok(())
+}
+
+fn write_schema(output: Option<&Path>) -> Result<()> {
+    let schema = serde_json::to_string_pretty(&config::json_schema())?;
+    match output {
+        Some(path) => std::fs::write(path, schema + "\n")
+            .with_context(|| format!("Failed to write schema: {}", path.display())),
+        None => {
+            println!("{}", schema);
+            Ok(())
+        }
+    }
+}
+
+fn migrate_config(path: &Path) -> Result<()> {
+    for file in config::config_files(path)? {
+        let Some((changes, backup)) = config::migrate_file(&file)? else {
+            info!("{} is already up to date", file.display());
+            continue;
+        };
+
+        for change in &changes {
+            info!("{}: {}", file.display(), change);
+        }
+        info!(
+            "Migrated {} to version {} (backup at {})",
+            file.display(),
+            config::CONFIG_VERSION,
+            backup.display()
+        );
+    }
+
+    Ok(())
+}
+
+async fn run_prune(path: &PathBuf, apply: bool) -> Result<()> {
+    info!("Loading configuration from: {}", path.display());
+    let config = Config::load(path)?;
+
+    if !config.settings.mark_managed {
+        warn!("settings.mark_managed is off - only records marked by earlier runs can be found");
+    }
+
+    let found = prune_orphans(&config, !apply).await?;
+    if found > 0 && !apply {
+        info!("Run with --apply to delete these records");
+    }
+
+    Ok(())
+}
+
+async fn prune_orphans(config: &Config, dry_run: bool) -> Result<usize> {
+    if !config.uses_provider(Provider::Cloudflare) && config.settings.prune_zones.is_empty() {
+        return Ok(0);
+    }
+
+    let client = DdnsClient::new(&config.cloudflare.api_token)?;
+    let found = prune::prune(&client, config, dry_run).await?;
+    if found > 0 && !dry_run {
+        info!("Pruned {} orphaned record(s)", found);
+    }
+    Ok(found)
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -195,8 +366,26 @@ async fn run_with_args(
 }
 
 async fn run_update(config: &Config, dry_run: bool) -> Result<()> {
-    // Create Cloudflare client
-    let client = DdnsClient::new(&config.cloudflare.api_token)?;
+    // Create provider clients; a one-shot run has no earlier webhook requests to skip
+    let providers = Providers::new(config, &SentRequests::default())?;
+
+    // Detect each address family once for all records and targets
+    let ips = match config.settings.force_ip {
+        Some(_) => PublicIps::default(),
+        None => {
+            let record_types = config
+                .records
+                .iter()
+                .map(|record| record.ip_record_type())
+                .chain(config.targets().map(|target| target.ip_record_type()));
+            PublicIps::detect(
+                record_types,
+                &config.settings.ipv4_url,
+                &config.settings.ipv6_url,
+            )
+            .await
+        }
+    };
 
     // Process each record
     let mut success_count = 0;
@@ -211,22 +400,14 @@ async fn run_update(config: &Config, dry_run: bool) -> Result<()> {
                 info!("Using forced IP: {}", ip);
                 ip
             }
-            None => {
-                match get_public_ip(
-                    record.record_type,
-                    &config.settings.ipv4_url,
-                    &config.settings.ipv6_url,
-                )
-                .await
-                {
-                    Ok(ip) => ip,
-                    Err(e) => {
-                        error!("Failed to get public IP for {}: {}", record.name, e);
-                        error_count += 1;
-                        continue;
-                    }
+            None => match ips.get(record.ip_record_type()) {
+                Ok(ip) => ip,
+                Err(e) => {
+                    error!("Failed to get public IP for {}: {}", record.name, e);
+                    error_count += 1;
+                    continue;
                 }
-            }
+            },
         };
 
         if dry_run {
@@ -236,7 +417,7 @@ async fn run_update(config: &Config, dry_run: bool) -> Result<()> {
         }
 
         // Update the record
-        match client.update_ddns(record, ip).await {
+        match providers.update_ddns(record, ip).await {
             Ok(result) => {
                 match result {
                     UpdateResult::Created => {
@@ -265,6 +446,18 @@ async fn run_update(config: &Config, dry_run: bool) -> Result<()> {
         }
     }
 
+    // Process each IP list and origin
+    for target in config.targets() {
+        if service::update_target(config, &providers, &ips, target, &None, dry_run)
+            .await
+            .success
+        {
+            success_count += 1;
+        } else {
+            error_count += 1;
+        }
+    }
+
     // Summary
     info!(
         "Completed: {} successful, {} failed",
@@ -0,0 +1,345 @@
+use anyhow::{Context, Result};
+use netlink_sys::{protocols::NETLINK_ROUTE, AsyncSocket, AsyncSocketExt, SocketAddr, TokioSocket};
+use std::collections::HashSet;
+use std::net::IpAddr;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+/// Address and route notifications of both families
+const GROUPS: u32 = (libc::RTMGRP_IPV4_IFADDR
+    | libc::RTMGRP_IPV6_IFADDR
+    | libc::RTMGRP_IPV4_ROUTE
+    | libc::RTMGRP_IPV6_ROUTE) as u32;
+
+/// End of a dump
+const NLMSG_DONE: u16 = libc::NLMSG_DONE as u16;
+/// Error or acknowledgement
+const NLMSG_ERROR: u16 = libc::NLMSG_ERROR as u16;
+
+/// Size of `struct nlmsghdr`
+const MESSAGE_HEADER_LEN: usize = 16;
+/// Size of `struct ifaddrmsg`
+const ADDRESS_HEADER_LEN: usize = 8;
+/// Size of `struct rtmsg`
+const ROUTE_HEADER_LEN: usize = 12;
+/// Size of `struct rtattr`
+const ATTRIBUTE_HEADER_LEN: usize = 4;
+
+/// Something the public IP may depend on
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum NetworkItem {
+    /// Global address of an interface
+    Address { interface: u32, address: IpAddr },
+    /// Default route of the main table
+    DefaultRoute {
+        interface: Option<u32>,
+        gateway: Option<IpAddr>,
+    },
+}
+
+/// Send on `tx` whenever a global address or a default route appears or goes away
+///
+/// Items already seen are remembered, so the kernel re-announcing them (e.g.
+/// when IPv6 lifetimes are refreshed) isn't reported as a change.
+pub async fn watch(tx: mpsc::Sender<()>) -> Result<()> {
+    let mut socket = TokioSocket::new(NETLINK_ROUTE).context("Failed to open a netlink socket")?;
+    socket
+        .socket_mut()
+        .bind(&SocketAddr::new(0, GROUPS))
+        .context("Failed to subscribe to address and route changes")?;
+
+    // Subscribed first, so nothing that changes while reading is missed
+    let mut known = snapshot().await?;
+    loop {
+        let buf = match socket.recv_from_full().await {
+            Ok((buf, _)) => buf,
+            // The receive buffer overflowed and notifications were dropped
+            Err(e) if e.raw_os_error() == Some(libc::ENOBUFS) => {
+                warn!("Missed network notifications; reading addresses and routes again");
+                let current = snapshot().await?;
+                if current != known {
+                    known = current;
+                    if !notify(&tx) {
+                        return Ok(());
+                    }
+                }
+                continue;
+            }
+            Err(e) => return Err(e).context("Failed to read from the netlink socket"),
+        };
+
+        for (kind, payload) in split(&buf, MESSAGE_HEADER_LEN) {
+            let Some(item) = parse(kind, payload) else {
+                continue;
+            };
+            let changed = match kind {
+                libc::RTM_NEWADDR | libc::RTM_NEWROUTE => known.insert(item.clone()),
+                _ => {
+                    known.remove(&item);
+                    true
+                }
+            };
+            if !changed {
+                continue;
+            }
+
+            debug!("Network change: {:?} (message type {})", item, kind);
+            if !notify(&tx) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Report a change; false once nobody is listening anymore
+fn notify(tx: &mpsc::Sender<()>) -> bool {
+    // A full channel already has an update pending
+    tx.try_send(()).is_ok() || !tx.is_closed()
+}
+
+/// The global addresses and default routes the system has right now
+async fn snapshot() -> Result<HashSet<NetworkItem>> {
+    let mut socket = TokioSocket::new(NETLINK_ROUTE).context("Failed to open a netlink socket")?;
+    socket
+        .socket_mut()
+        .bind_auto()
+        .context("Failed to bind a netlink socket")?;
+
+    let mut items = HashSet::new();
+    for (kind, header_len) in [
+        (libc::RTM_GETADDR, ADDRESS_HEADER_LEN),
+        (libc::RTM_GETROUTE, ROUTE_HEADER_LEN),
+    ] {
+        socket
+            .send_to(&dump_request(kind, header_len), &SocketAddr::new(0, 0))
+            .await
+            .context("Failed to request addresses and routes")?;
+
+        'dump: loop {
+            let (buf, _) = socket
+                .recv_from_full()
+                .await
+                .context("Failed to read addresses and routes")?;
+            for (kind, payload) in split(&buf, MESSAGE_HEADER_LEN) {
+                match kind {
+                    NLMSG_DONE => break 'dump,
+                    NLMSG_ERROR => anyhow::bail!("The kernel refused to list addresses and routes"),
+                    _ => items.extend(parse(kind, payload)),
+                }
+            }
+        }
+    }
+    Ok(items)
+}
+
+/// Request for every address or route of both families
+fn dump_request(kind: u16, header_len: usize) -> Vec<u8> {
+    let len = MESSAGE_HEADER_LEN + header_len;
+    let flags = (libc::NLM_F_REQUEST | libc::NLM_F_DUMP) as u16;
+
+    let mut request = Vec::with_capacity(len);
+    request.extend_from_slice(&(len as u32).to_ne_bytes());
+    request.extend_from_slice(&kind.to_ne_bytes());
+    request.extend_from_slice(&flags.to_ne_bytes());
+    request.extend_from_slice(&1u32.to_ne_bytes()); // Sequence number
+    request.extend_from_slice(&0u32.to_ne_bytes()); // Port ID, filled in by the kernel
+
+    // A zeroed ifaddrmsg/rtmsg: family AF_UNSPEC, no filters
+    request.resize(len, 0);
+    request
+}
+
+/// Address or default route described by an rtnetlink message
+fn parse(kind: u16, payload: &[u8]) -> Option<NetworkItem> {
+    match kind {
+        libc::RTM_NEWADDR | libc::RTM_DELADDR => {
+            let header = payload.get(..ADDRESS_HEADER_LEN)?;
+            let (family, scope) = (header[0], header[3]);
+            if scope != libc::RT_SCOPE_UNIVERSE {
+                return None;
+            }
+            let interface = u32::from_ne_bytes(header[4..8].try_into().ok()?);
+
+            let mut address = None;
+            for (kind, value) in split(&payload[ADDRESS_HEADER_LEN..], ATTRIBUTE_HEADER_LEN) {
+                match kind {
+                    // Our end of point-to-point links such as PPPoE; IFA_ADDRESS is the peer there
+                    libc::IFA_LOCAL => address = ip_addr(family, value),
+                    libc::IFA_ADDRESS if address.is_none() => address = ip_addr(family, value),
+                    _ => {}
+                }
+            }
+            Some(NetworkItem::Address {
+                interface,
+                address: address?,
+            })
+        }
+        libc::RTM_NEWROUTE | libc::RTM_DELROUTE => {
+            let header = payload.get(..ROUTE_HEADER_LEN)?;
+            let (family, dst_len, table, route_type) = (header[0], header[1], header[4], header[7]);
+            if dst_len != 0 || table != libc::RT_TABLE_MAIN || route_type != libc::RTN_UNICAST {
+                return None;
+            }
+
+            let (mut interface, mut gateway) = (None, None);
+            for (kind, value) in split(&payload[ROUTE_HEADER_LEN..], ATTRIBUTE_HEADER_LEN) {
+                match kind {
+                    libc::RTA_OIF => interface = value.try_into().ok().map(u32::from_ne_bytes),
+                    libc::RTA_GATEWAY => gateway = ip_addr(family, value),
+                    _ => {}
+                }
+            }
+            Some(NetworkItem::DefaultRoute { interface, gateway })
+        }
+        _ => None,
+    }
+}
+
+/// Split netlink messages or route attributes into (type, payload) pairs
+///
+/// Both start with a native-endian length and type (u32/u16 for messages,
+/// u16/u16 for attributes) and are padded to four bytes.
+fn split(mut buf: &[u8], header_len: usize) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        let header = buf.get(..header_len)?;
+        let (len, kind) = if header_len == MESSAGE_HEADER_LEN {
+            (
+                u32::from_ne_bytes(header[0..4].try_into().ok()?) as usize,
+                u16::from_ne_bytes(header[4..6].try_into().ok()?),
+            )
+        } else {
+            (
+                u16::from_ne_bytes(header[0..2].try_into().ok()?) as usize,
+                u16::from_ne_bytes(header[2..4].try_into().ok()?),
+            )
+        };
+        if len < header_len || len > buf.len() {
+            return None;
+        }
+
+        let payload = &buf[header_len..len];
+        buf = &buf[((len + 3) & !3).min(buf.len())..];
+        Some((kind, payload))
+    })
+}
+
+fn ip_addr(family: u8, value: &[u8]) -> Option<IpAddr> {
+    match i32::from(family) {
+        libc::AF_INET => <[u8; 4]>::try_from(value).ok().map(IpAddr::from),
+        libc::AF_INET6 => <[u8; 16]>::try_from(value).ok().map(IpAddr::from),
+        _ => None,
+    }
+}
+
+// Messages captured from an RTM_GETADDR/RTM_GETROUTE dump on a little-endian host
+#[cfg(all(test, target_endian = "little"))]
+mod tests {
+    use super::*;
+
+    /// 127.0.0.1/8 on lo (host scope)
+    const LOOPBACK_ADDRESS: &str = "4c0000001400020001000000f33e0000020880fe01000000080001007f000001080002007f000001070003006c6f0000080008008000000014000600ffffffffffffffff1000000010000000";
+    /// 192.0.2.2/24 on eth0 (interface 4, global scope)
+    const GLOBAL_ADDRESS: &str = "580000001400020001000000f33e0000021880000400000008000100c000020208000200c000020208000400c00002ff090003006574683000000000080008008000000014000600ffffffffffffffff1000000010000000";
+    /// fe80::fc:ff:fe00:1/64 on eth0 (link scope)
+    const LINK_LOCAL_ADDRESS: &str = "500000001400020001000000f33e00000a4080fd0400000014000100fe8000000000000000fc00fffe00000114000600ffffffffffffffff1000000010000000080008008000000005000b0003000000";
+    /// default via 192.0.2.1 dev eth0
+    const DEFAULT_ROUTE: &str = "340000001800020001000000f33e000002000000fe0300010000000008000f00fe00000008000500c00002010800040004000000";
+    /// 192.0.2.0/24 dev eth0
+    const SUBNET_ROUTE: &str = "3c0000001800020001000000f33e000002180000fe02fd010000000008000f00fe00000008000100c000020008000700c00002020800040004000000";
+    /// local 127.0.0.1 dev lo (local table)
+    const LOCAL_ROUTE: &str = "3c0000001800020001000000f33e000002200000ff02fe020000000008000f00ff000000080001007f000001080007007f0000010800040001000000";
+
+    fn hex(text: &str) -> Vec<u8> {
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn parse_one(text: &str) -> Option<NetworkItem> {
+        let buf = hex(text);
+        let (kind, payload) = split(&buf, MESSAGE_HEADER_LEN).next().unwrap();
+        parse(kind, payload)
+    }
+
+    #[test]
+    fn splits_messages() {
+        let mut buf = Vec::new();
+        for message in [LOOPBACK_ADDRESS, GLOBAL_ADDRESS, LINK_LOCAL_ADDRESS] {
+            buf.extend(hex(message));
+        }
+
+        let messages: Vec<_> = split(&buf, MESSAGE_HEADER_LEN).collect();
+        assert_eq!(messages.len(), 3);
+        assert!(messages.iter().all(|(kind, _)| *kind == libc::RTM_NEWADDR));
+        assert_eq!(messages[1].1.len(), 88 - MESSAGE_HEADER_LEN);
+    }
+
+    #[test]
+    fn splits_attributes() {
+        let buf = hex(DEFAULT_ROUTE);
+        let attributes: Vec<_> = split(&buf[MESSAGE_HEADER_LEN + ROUTE_HEADER_LEN..], 4)
+            .map(|(kind, value)| (kind, value.to_vec()))
+            .collect();
+        assert_eq!(
+            attributes,
+            [
+                (libc::RTA_TABLE, vec![254, 0, 0, 0]),
+                (libc::RTA_GATEWAY, vec![192, 0, 2, 1]),
+                (libc::RTA_OIF, vec![4, 0, 0, 0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_at_truncated_messages() {
+        let mut buf = hex(GLOBAL_ADDRESS);
+        buf.extend(&hex(DEFAULT_ROUTE)[..20]);
+        assert_eq!(split(&buf, MESSAGE_HEADER_LEN).count(), 1);
+        assert_eq!(split(&buf[..10], MESSAGE_HEADER_LEN).count(), 0);
+    }
+
+    #[test]
+    fn parses_global_addresses() {
+        assert_eq!(
+            parse_one(GLOBAL_ADDRESS),
+            Some(NetworkItem::Address {
+                interface: 4,
+                address: "192.0.2.2".parse().unwrap(),
+            })
+        );
+        assert_eq!(parse_one(LOOPBACK_ADDRESS), None);
+        assert_eq!(parse_one(LINK_LOCAL_ADDRESS), None);
+    }
+
+    #[test]
+    fn parses_default_routes() {
+        assert_eq!(
+            parse_one(DEFAULT_ROUTE),
+            Some(NetworkItem::DefaultRoute {
+                interface: Some(4),
+                gateway: Some("192.0.2.1".parse().unwrap()),
+            })
+        );
+        assert_eq!(parse_one(SUBNET_ROUTE), None);
+        assert_eq!(parse_one(LOCAL_ROUTE), None);
+    }
+
+    #[test]
+    fn parses_removals_like_additions() {
+        let mut buf = hex(GLOBAL_ADDRESS);
+        buf[4..6].copy_from_slice(&libc::RTM_DELADDR.to_ne_bytes());
+        let (kind, payload) = split(&buf, MESSAGE_HEADER_LEN).next().unwrap();
+        assert_eq!(kind, libc::RTM_DELADDR);
+        assert_eq!(parse(kind, payload), parse_one(GLOBAL_ADDRESS));
+    }
+
+    #[test]
+    fn builds_dump_requests() {
+        let request = dump_request(libc::RTM_GETROUTE, ROUTE_HEADER_LEN);
+        let (kind, payload) = split(&request, MESSAGE_HEADER_LEN).next().unwrap();
+        assert_eq!(kind, libc::RTM_GETROUTE);
+        assert_eq!(payload, [0; ROUTE_HEADER_LEN]);
+        assert_eq!(request[6..8], 0x301u16.to_ne_bytes());
+    }
+}
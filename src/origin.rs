@@ -0,0 +1,145 @@
+use anyhow::{Context, Result};
+use cloudflare::framework::endpoint::spec::EndpointSpec;
+use cloudflare::framework::endpoint::{Method, RequestBody};
+use cloudflare::framework::response::{ApiResult, ApiSuccess};
+use serde::Deserialize;
+use serde_json::Value;
+use std::net::IpAddr;
+use tracing::{debug, info};
+
+use crate::cloudflare::{api_client, ApiClient};
+use crate::config::OriginConfig;
+use crate::provider::UpdateResult;
+
+/// Client keeping Load Balancer pool origins at the dynamic IP
+pub struct OriginClient {
+    client: ApiClient,
+}
+
+impl OriginClient {
+    /// Create a new origin client with the given API token
+    pub fn new(api_token: &str) -> Result<Self> {
+        Ok(Self {
+            client: api_client(api_token)?,
+        })
+    }
+
+    /// Point the configured origin at the given IP
+    pub async fn update_origin(&self, origin: &OriginConfig, ip: IpAddr) -> Result<UpdateResult> {
+        let account_id = origin.account_id.as_str();
+        let pool = self.find_pool(account_id, &origin.pool).await?;
+
+        // Origins are patched as a whole, so keep every field we don't manage
+        let mut origins = pool.origins;
+        let entry = origins
+            .iter_mut()
+            .find(|o| o.get("name").and_then(Value::as_str) == Some(origin.origin.as_str()))
+            .with_context(|| format!("Origin {} not found in pool {}", origin.origin, pool.name))?;
+
+        let current = entry
+            .get("address")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        let old_ip = current.parse::<IpAddr>().ok();
+        if old_ip == Some(ip) {
+            debug!("Origin {} already points to {}", origin.origin, ip);
+            return Ok(UpdateResult::Unchanged);
+        }
+
+        entry["address"] = Value::String(ip.to_string());
+
+        self.client
+            .request(&PatchPoolOrigins {
+                account_identifier: account_id,
+                identifier: &pool.id,
+                origins: &origins,
+            })
+            .await
+            .context("Failed to update pool origins")?;
+
+        info!(
+            "Updated origin {} in pool {}: {} -> {}",
+            origin.origin, pool.name, current, ip
+        );
+        Ok(UpdateResult::Updated { old_ip, new_ip: ip })
+    }
+
+    /// Find a pool by name or ID
+    async fn find_pool(&self, account_id: &str, pool: &str) -> Result<PoolOrigins> {
+        let response = self
+            .client
+            .request(&ListPools {
+                account_identifier: account_id,
+            })
+            .await
+            .context("Failed to list load balancer pools")?;
+
+        response
+            .result
+            .0
+            .into_iter()
+            .find(|p| p.name == pool || p.id == pool)
+            .with_context(|| format!("Load balancer pool not found: {}", pool))
+    }
+}
+
+/// Pool with its origins kept as raw JSON
+#[derive(Deserialize, Debug)]
+struct PoolOrigins {
+    id: String,
+    name: String,
+    #[serde(default)]
+    origins: Vec<Value>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(transparent)]
+struct Pools(Vec<PoolOrigins>);
+
+impl ApiResult for PoolOrigins {}
+impl ApiResult for Pools {}
+
+/// List the account's load balancer pools
+struct ListPools<'a> {
+    account_identifier: &'a str,
+}
+
+impl EndpointSpec for ListPools<'_> {
+    type JsonResponse = Pools;
+    type ResponseType = ApiSuccess<Self::JsonResponse>;
+
+    fn method(&self) -> Method {
+        Method::GET
+    }
+    fn path(&self) -> String {
+        format!("accounts/{}/load_balancers/pools", self.account_identifier)
+    }
+}
+
+/// Replace the origins of a pool
+struct PatchPoolOrigins<'a> {
+    account_identifier: &'a str,
+    identifier: &'a str,
+    origins: &'a [Value],
+}
+
+impl EndpointSpec for PatchPoolOrigins<'_> {
+    type JsonResponse = PoolOrigins;
+    type ResponseType = ApiSuccess<Self::JsonResponse>;
+
+    fn method(&self) -> Method {
+        Method::PATCH
+    }
+    fn path(&self) -> String {
+        format!(
+            "accounts/{}/load_balancers/pools/{}",
+            self.account_identifier, self.identifier
+        )
+    }
+    fn body(&self) -> Option<RequestBody<'_>> {
+        serde_json::to_string(&serde_json::json!({ "origins": self.origins }))
+            .ok()
+            .map(RequestBody::Json)
+    }
+}
@@ -0,0 +1,133 @@
+use anyhow::{Context, Result};
+use std::net::IpAddr;
+
+use crate::cloudflare::DdnsClient;
+use crate::config::{Config, Provider, RecordConfig, Target};
+use crate::ip_list::IpListClient;
+use crate::origin::OriginClient;
+use crate::rfc2136::Rfc2136Client;
+use crate::webhook::{SentRequests, WebhookClient};
+
+/// Clients for every provider referenced by a configuration
+pub struct Providers {
+    cloudflare: Option<DdnsClient>,
+    rfc2136: Option<Rfc2136Client>,
+    webhook: Option<WebhookClient>,
+    ip_list: Option<IpListClient>,
+    origin: Option<OriginClient>,
+}
+
+impl Providers {
+    /// Create the clients needed by the configured records
+    ///
+    /// `sent` is the webhook dedup cache, shared across cycles until the config changes.
+    pub fn new(config: &Config, sent: &SentRequests) -> Result<Self> {
+        // Pruning may also need the client for zones no record uses anymore
+        let prunes_zones = config.settings.prune_orphans && !config.settings.prune_zones.is_empty();
+        let cloudflare = if config.uses_provider(Provider::Cloudflare) || prunes_zones {
+            Some(
+                DdnsClient::new(&config.cloudflare.api_token)?
+                    .with_managed_marker(config.settings.mark_managed, &config.settings.owner_id),
+            )
+        } else {
+            None
+        };
+
+        let rfc2136 = match (&config.rfc2136, config.uses_provider(Provider::Rfc2136)) {
+            (Some(rfc2136), true) => Some(Rfc2136Client::new(rfc2136)?),
+            _ => None,
+        };
+
+        let webhook = if config.uses_provider(Provider::Webhook) {
+            Some(WebhookClient::new(&config.webhooks, sent))
+        } else {
+            None
+        };
+
+        let ip_list = if config.ip_lists.is_empty() {
+            None
+        } else {
+            Some(IpListClient::new(&config.cloudflare.api_token)?)
+        };
+
+        let origin = if config.origins.is_empty() {
+            None
+        } else {
+            Some(OriginClient::new(&config.cloudflare.api_token)?)
+        };
+
+        Ok(Self {
+            cloudflare,
+            rfc2136,
+            webhook,
+            ip_list,
+            origin,
+        })
+    }
+
+    /// The Cloudflare client, if any record uses Cloudflare
+    pub fn cloudflare(&self) -> Option<&DdnsClient> {
+        self.cloudflare.as_ref()
+    }
+
+    /// Point an IP list entry or pool origin at the given IP
+    pub async fn update_target(&self, target: Target<'_>, ip: IpAddr) -> Result<UpdateResult> {
+        match target {
+            Target::IpList(list) => {
+                self.ip_list
+                    .as_ref()
+                    .context("IP list client is not configured")?
+                    .update_ip_list(list, ip)
+                    .await
+            }
+            Target::Origin(origin) => {
+                self.origin
+                    .as_ref()
+                    .context("Origin client is not configured")?
+                    .update_origin(origin, ip)
+                    .await
+            }
+        }
+    }
+
+    /// Update a record through its provider
+    pub async fn update_ddns(&self, record: &RecordConfig, ip: IpAddr) -> Result<UpdateResult> {
+        match record.provider {
+            Provider::Cloudflare => {
+                self.cloudflare
+                    .as_ref()
+                    .context("Cloudflare client is not configured")?
+                    .update_ddns(record, ip)
+                    .await
+            }
+            Provider::Rfc2136 => {
+                self.rfc2136
+                    .as_ref()
+                    .context("RFC 2136 client is not configured")?
+                    .update_ddns(record, ip)
+                    .await
+            }
+            Provider::Webhook => {
+                self.webhook
+                    .as_ref()
+                    .context("Webhook client is not configured")?
+                    .update_ddns(record, ip)
+                    .await
+            }
+        }
+    }
+}
+
+/// Result of a DDNS update operation
+#[derive(Debug)]
+pub enum UpdateResult {
+    /// Record was created (didn't exist before)
+    Created,
+    /// Record was updated with new IP
+    Updated {
+        old_ip: Option<IpAddr>,
+        new_ip: IpAddr,
+    },
+    /// Record already had the correct IP
+    Unchanged,
+}
@@ -0,0 +1,79 @@
+use anyhow::Result;
+use std::collections::{BTreeMap, HashSet};
+use tracing::info;
+
+use crate::cloudflare::{is_owned_by, DdnsClient, DnsRecord};
+use crate::config::Config;
+
+/// A Cloudflare record carrying our cddns marker that no longer appears in the config
+pub struct OrphanRecord {
+    pub zone: String,
+    pub zone_id: String,
+    pub record: DnsRecord,
+}
+
+/// Find managed records that are no longer configured
+///
+/// The zones of the configured records and `prune_zones` are searched. Only
+/// records stamped with this instance's `owner_id` are considered, so records
+/// created by hand, by other tools or by other cddns instances are never touched.
+pub async fn find_orphans(client: &DdnsClient, config: &Config) -> Result<Vec<OrphanRecord>> {
+    // Configured (name, type) pairs, grouped by zone
+    let mut zones: BTreeMap<String, HashSet<(String, String)>> = BTreeMap::new();
+    for zone in &config.settings.prune_zones {
+        zones.entry(normalize(zone)).or_default();
+    }
+    for record in config.records.iter().filter(|r| r.provider.is_cloudflare()) {
+        zones
+            .entry(normalize(&record.zone))
+            .or_default()
+            .insert((normalize(&record.name), record.record_type.to_string()));
+    }
+
+    let mut orphans = Vec::new();
+    for (zone, configured) in zones {
+        let zone_id = client.get_zone_id(&zone).await?;
+        for record in client.list_records(&zone_id).await? {
+            let key = (record.name.to_lowercase(), record.record_type.clone());
+            if is_owned_by(&record, &config.settings.owner_id) && !configured.contains(&key) {
+                orphans.push(OrphanRecord {
+                    zone: zone.clone(),
+                    zone_id: zone_id.clone(),
+                    record,
+                });
+            }
+        }
+    }
+
+    Ok(orphans)
+}
+
+/// Find orphaned records and delete them unless `dry_run` is set
+///
+/// Returns the number of orphans found.
+pub async fn prune(client: &DdnsClient, config: &Config, dry_run: bool) -> Result<usize> {
+    let orphans = find_orphans(client, config).await?;
+
+    if orphans.is_empty() {
+        info!("No orphaned records found");
+        return Ok(0);
+    }
+
+    for orphan in &orphans {
+        let record = &orphan.record;
+        if dry_run {
+            info!(
+                "[DRY-RUN] Would delete orphaned {} record: {} -> {} (zone {})",
+                record.record_type, record.name, record.content, orphan.zone
+            );
+        } else {
+            client.delete_record(&orphan.zone_id, record).await?;
+        }
+    }
+
+    Ok(orphans.len())
+}
+
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_lowercase()
+}
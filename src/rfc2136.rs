@@ -0,0 +1,772 @@
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::UdpSocket;
+use tracing::{debug, info, warn};
+
+use crate::config::{RecordConfig, Rfc2136Config};
+use crate::provider::UpdateResult;
+
+/// DNS UPDATE opcode (RFC 2136)
+const OPCODE_UPDATE: u16 = 5;
+
+const CLASS_IN: u16 = 1;
+const CLASS_ANY: u16 = 255;
+
+const TYPE_A: u16 = 1;
+const TYPE_SOA: u16 = 6;
+const TYPE_AAAA: u16 = 28;
+const TYPE_TSIG: u16 = 250;
+
+/// The only supported TSIG algorithm
+const TSIG_ALGORITHM: &str = "hmac-sha256";
+
+/// Allowed clock skew for TSIG signatures, in seconds
+const TSIG_FUDGE: u16 = 300;
+
+/// TTL used when a record asks for Cloudflare's "automatic" TTL (1)
+const DEFAULT_TTL: u32 = 300;
+
+/// How long to wait for the server to answer
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// RFC 2136 dynamic update client with TSIG (HMAC-SHA256) authentication
+pub struct Rfc2136Client {
+    server: String,
+    key_name: String,
+    key: Vec<u8>,
+}
+
+impl Rfc2136Client {
+    /// Create a new client from the `[rfc2136]` configuration
+    pub fn new(config: &Rfc2136Config) -> Result<Self> {
+        let key = BASE64
+            .decode(config.key_secret.trim())
+            .context("Failed to decode TSIG key secret (expected base64)")?;
+
+        Ok(Self {
+            server: config.server.clone(),
+            key_name: config.key_name.clone(),
+            key,
+        })
+    }
+
+    /// Update a DNS record configuration with the given IP
+    /// Creates the record if it doesn't exist, replaces it if the IP has changed
+    pub async fn update_ddns(
+        &self,
+        record_config: &RecordConfig,
+        ip: IpAddr,
+    ) -> Result<UpdateResult> {
+        let server = self.resolve_server().await?;
+        let (rtype, rdata) = match ip {
+            IpAddr::V4(ip) => (TYPE_A, ip.octets().to_vec()),
+            IpAddr::V6(ip) => (TYPE_AAAA, ip.octets().to_vec()),
+        };
+        let name = &record_config.name;
+        let zone = &record_config.zone;
+
+        // Prerequisite only: the RRset is exactly { ip }
+        let mut msg = UpdateMessage::new(zone);
+        msg.prerequisite(name, rtype, CLASS_IN, 0, &rdata);
+        match self.send(server, msg).await? {
+            Rcode::NoError => {
+                debug!("{} already points to {}, skipping", name, ip);
+                return Ok(UpdateResult::Unchanged);
+            }
+            Rcode::NxRrset => {}
+            rcode => anyhow::bail!("Server rejected prerequisite check for {}: {}", name, rcode),
+        }
+
+        // Prerequisite only: the RRset exists with any value
+        let mut msg = UpdateMessage::new(zone);
+        msg.prerequisite(name, rtype, CLASS_ANY, 0, &[]);
+        let exists = match self.send(server, msg).await? {
+            Rcode::NoError => true,
+            Rcode::NxRrset => false,
+            rcode => anyhow::bail!("Server rejected existence check for {}: {}", name, rcode),
+        };
+
+        if !exists {
+            warn!(
+                "Record {} not found, creating new {} record",
+                name, record_config.record_type
+            );
+        }
+
+        // Replace the RRset with the new address
+        let ttl = if record_config.ttl == 1 {
+            DEFAULT_TTL
+        } else {
+            record_config.ttl
+        };
+        let mut msg = UpdateMessage::new(zone);
+        msg.update(name, rtype, CLASS_ANY, 0, &[]);
+        msg.update(name, rtype, CLASS_IN, ttl, &rdata);
+        match self.send(server, msg).await? {
+            Rcode::NoError => {}
+            rcode => anyhow::bail!("Server rejected update for {}: {}", name, rcode),
+        }
+
+        info!("Updated {} -> {} via {}", name, ip, self.server);
+
+        if exists {
+            Ok(UpdateResult::Updated {
+                old_ip: None,
+                new_ip: ip,
+            })
+        } else {
+            Ok(UpdateResult::Created)
+        }
+    }
+
+    /// Resolve the configured server, defaulting to port 53
+    async fn resolve_server(&self) -> Result<SocketAddr> {
+        if let Ok(addr) = self.server.parse::<SocketAddr>() {
+            return Ok(addr);
+        }
+        if let Ok(ip) = self.server.parse::<IpAddr>() {
+            return Ok(SocketAddr::new(ip, 53));
+        }
+
+        let host = if self.server.contains(':') {
+            self.server.clone()
+        } else {
+            format!("{}:53", self.server)
+        };
+
+        tokio::net::lookup_host(host)
+            .await
+            .with_context(|| format!("Failed to resolve RFC 2136 server: {}", self.server))?
+            .next()
+            .with_context(|| format!("No address found for RFC 2136 server: {}", self.server))
+    }
+
+    /// Sign and send a message, returning the response code
+    async fn send(&self, server: SocketAddr, msg: UpdateMessage) -> Result<Rcode> {
+        let id: u16 = rand::random();
+        let mut packet = msg.encode(id)?;
+        let request_mac = self.sign(&mut packet, None, unix_time()?)?;
+
+        let bind_addr = if server.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(bind_addr)
+            .await
+            .context("Failed to bind UDP socket")?;
+        socket
+            .send_to(&packet, server)
+            .await
+            .with_context(|| format!("Failed to send DNS update to {}", server))?;
+
+        let mut buf = [0u8; 4096];
+        loop {
+            let (len, from) = tokio::time::timeout(TIMEOUT, socket.recv_from(&mut buf))
+                .await
+                .with_context(|| format!("Timed out waiting for response from {}", server))?
+                .context("Failed to receive DNS response")?;
+
+            // Ignore stray datagrams that don't answer our query
+            if from != server || len < 12 || u16::from_be_bytes([buf[0], buf[1]]) != id {
+                continue;
+            }
+            if buf[2] & 0x80 == 0 {
+                continue;
+            }
+
+            let rcode = Rcode::from(buf[3] & 0x0f);
+            self.verify(&buf[..len], Some(&request_mac), unix_time()?)
+                .with_context(|| format!("Rejected response from {} ({})", server, rcode))?;
+            return Ok(rcode);
+        }
+    }
+
+    /// Append a TSIG record (RFC 8945) to an encoded message, returning its MAC
+    ///
+    /// Responses also cover the MAC of the request they answer.
+    fn sign(
+        &self,
+        packet: &mut Vec<u8>,
+        request_mac: Option<&[u8]>,
+        time_signed: u64,
+    ) -> Result<Vec<u8>> {
+        let id = u16::from_be_bytes([packet[0], packet[1]]);
+        let mac = self
+            .digest(request_mac, packet, time_signed, TSIG_FUDGE, 0, &[])?
+            .finalize()
+            .into_bytes();
+
+        let mut rdata = encode_name(TSIG_ALGORITHM)?;
+        rdata.extend_from_slice(&time_signed.to_be_bytes()[2..]);
+        rdata.extend_from_slice(&TSIG_FUDGE.to_be_bytes());
+        rdata.extend_from_slice(&(mac.len() as u16).to_be_bytes());
+        rdata.extend_from_slice(&mac);
+        rdata.extend_from_slice(&id.to_be_bytes());
+        rdata.extend_from_slice(&0u16.to_be_bytes()); // Error
+        rdata.extend_from_slice(&0u16.to_be_bytes()); // Other len
+
+        packet.extend_from_slice(&encode_name(&self.key_name.to_lowercase())?);
+        packet.extend_from_slice(&TYPE_TSIG.to_be_bytes());
+        packet.extend_from_slice(&CLASS_ANY.to_be_bytes());
+        packet.extend_from_slice(&0u32.to_be_bytes());
+        packet.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&rdata);
+
+        // Bump ARCOUNT to include the TSIG record
+        let arcount = u16::from_be_bytes([packet[10], packet[11]]) + 1;
+        packet[10..12].copy_from_slice(&arcount.to_be_bytes());
+
+        Ok(mac.to_vec())
+    }
+
+    /// Check the TSIG record ending a signed message (RFC 8945 §5.3, §5.4)
+    ///
+    /// `request_mac` is the MAC of our request when checking its response.
+    fn verify(&self, packet: &[u8], request_mac: Option<&[u8]>, now: u64) -> Result<()> {
+        let (start, tsig) = find_tsig(packet).context("Message is not signed with TSIG")?;
+
+        if tsig.key_name != encode_name(&self.key_name.to_lowercase())?
+            || tsig.algorithm != encode_name(TSIG_ALGORITHM)?
+        {
+            anyhow::bail!("Message is signed with a different TSIG key");
+        }
+        if tsig.error != 0 {
+            anyhow::bail!("Server reported TSIG error {}", tsig_error(tsig.error));
+        }
+        // Truncated MACs must keep at least half of the hash (RFC 8945 §5.2.2.1)
+        if tsig.mac.len() < 16 || tsig.mac.len() > 32 {
+            anyhow::bail!("TSIG MAC has an invalid length of {}", tsig.mac.len());
+        }
+
+        // The message as it was before signing: without the TSIG record,
+        // with the original ID and ARCOUNT
+        let mut message = packet[..start].to_vec();
+        message[0..2].copy_from_slice(&tsig.original_id.to_be_bytes());
+        let arcount = u16::from_be_bytes([message[10], message[11]]) - 1;
+        message[10..12].copy_from_slice(&arcount.to_be_bytes());
+
+        self.digest(
+            request_mac,
+            &message,
+            tsig.time_signed,
+            tsig.fudge,
+            tsig.error,
+            &tsig.other,
+        )?
+        .verify_truncated_left(&tsig.mac)
+        .map_err(|_| anyhow::anyhow!("TSIG signature does not match (check the key secret)"))?;
+
+        if now.abs_diff(tsig.time_signed) > u64::from(tsig.fudge) {
+            anyhow::bail!("TSIG time is outside the allowed clock skew (check the system clock)");
+        }
+
+        Ok(())
+    }
+
+    /// HMAC over a message and its TSIG variables (RFC 8945 §4.3)
+    fn digest(
+        &self,
+        request_mac: Option<&[u8]>,
+        message: &[u8],
+        time_signed: u64,
+        fudge: u16,
+        error: u16,
+        other: &[u8],
+    ) -> Result<Hmac<Sha256>> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).context("Invalid TSIG key")?;
+        if let Some(request_mac) = request_mac {
+            mac.update(&(request_mac.len() as u16).to_be_bytes());
+            mac.update(request_mac);
+        }
+        mac.update(message);
+        mac.update(&encode_name(&self.key_name.to_lowercase())?);
+        mac.update(&CLASS_ANY.to_be_bytes());
+        mac.update(&0u32.to_be_bytes()); // TTL
+        mac.update(&encode_name(TSIG_ALGORITHM)?);
+        mac.update(&time_signed.to_be_bytes()[2..]);
+        mac.update(&fudge.to_be_bytes());
+        mac.update(&error.to_be_bytes());
+        mac.update(&(other.len() as u16).to_be_bytes());
+        mac.update(other);
+        Ok(mac)
+    }
+}
+
+fn unix_time() -> Result<u64> {
+    Ok(SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .context("System clock is before the Unix epoch")?
+        .as_secs())
+}
+
+/// A DNS UPDATE message under construction
+struct UpdateMessage {
+    zone: String,
+    prerequisites: Vec<ResourceRecord>,
+    updates: Vec<ResourceRecord>,
+}
+
+struct ResourceRecord {
+    name: String,
+    rtype: u16,
+    class: u16,
+    ttl: u32,
+    rdata: Vec<u8>,
+}
+
+impl UpdateMessage {
+    fn new(zone: &str) -> Self {
+        Self {
+            zone: zone.to_string(),
+            prerequisites: Vec::new(),
+            updates: Vec::new(),
+        }
+    }
+
+    fn prerequisite(&mut self, name: &str, rtype: u16, class: u16, ttl: u32, rdata: &[u8]) {
+        self.prerequisites.push(ResourceRecord {
+            name: name.to_string(),
+            rtype,
+            class,
+            ttl,
+            rdata: rdata.to_vec(),
+        });
+    }
+
+    fn update(&mut self, name: &str, rtype: u16, class: u16, ttl: u32, rdata: &[u8]) {
+        self.updates.push(ResourceRecord {
+            name: name.to_string(),
+            rtype,
+            class,
+            ttl,
+            rdata: rdata.to_vec(),
+        });
+    }
+
+    /// Encode the message in wire format
+    fn encode(&self, id: u16) -> Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(512);
+        buf.extend_from_slice(&id.to_be_bytes());
+        buf.extend_from_slice(&(OPCODE_UPDATE << 11).to_be_bytes());
+        buf.extend_from_slice(&1u16.to_be_bytes()); // ZOCOUNT
+        buf.extend_from_slice(&(self.prerequisites.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&(self.updates.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&0u16.to_be_bytes()); // ADCOUNT
+
+        // Zone section
+        buf.extend_from_slice(&encode_name(&self.zone)?);
+        buf.extend_from_slice(&TYPE_SOA.to_be_bytes());
+        buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+        for rr in self.prerequisites.iter().chain(&self.updates) {
+            buf.extend_from_slice(&encode_name(&rr.name)?);
+            buf.extend_from_slice(&rr.rtype.to_be_bytes());
+            buf.extend_from_slice(&rr.class.to_be_bytes());
+            buf.extend_from_slice(&rr.ttl.to_be_bytes());
+            buf.extend_from_slice(&(rr.rdata.len() as u16).to_be_bytes());
+            buf.extend_from_slice(&rr.rdata);
+        }
+
+        Ok(buf)
+    }
+}
+
+/// Encode a domain name in uncompressed wire format
+fn encode_name(name: &str) -> Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(name.len() + 2);
+    let name = name.trim_end_matches('.');
+
+    if !name.is_empty() {
+        for label in name.split('.') {
+            if label.is_empty() || label.len() > 63 {
+                anyhow::bail!("Invalid DNS name: {}", name);
+            }
+            buf.push(label.len() as u8);
+            buf.extend_from_slice(label.as_bytes());
+        }
+    }
+    buf.push(0);
+
+    if buf.len() > 255 {
+        anyhow::bail!("DNS name too long: {}", name);
+    }
+
+    Ok(buf)
+}
+
+/// TSIG record of a received message
+struct Tsig {
+    /// Key name in canonical wire format
+    key_name: Vec<u8>,
+    /// Algorithm name in canonical wire format
+    algorithm: Vec<u8>,
+    time_signed: u64,
+    fudge: u16,
+    mac: Vec<u8>,
+    original_id: u16,
+    error: u16,
+    other: Vec<u8>,
+}
+
+/// The TSIG record ending a message and the offset it starts at
+///
+/// `None` if the message is malformed or its last record isn't a TSIG.
+fn find_tsig(packet: &[u8]) -> Option<(usize, Tsig)> {
+    let u16_at = |pos: usize| -> Option<u16> {
+        Some(u16::from_be_bytes(
+            packet.get(pos..pos + 2)?.try_into().ok()?,
+        ))
+    };
+    let count = |pos: usize| u16_at(pos).map(usize::from);
+
+    let records = count(6)? + count(8)? + count(10)?;
+    if count(10)? == 0 {
+        return None;
+    }
+
+    let mut pos = 12;
+    for _ in 0..count(4)? {
+        pos = skip_name(packet, pos)? + 4;
+    }
+    let mut start = pos;
+    for _ in 0..records {
+        start = pos;
+        let fixed = skip_name(packet, pos)?;
+        pos = fixed + 10 + usize::from(u16_at(fixed + 8)?);
+    }
+    if pos != packet.len() {
+        return None;
+    }
+
+    let fixed = skip_name(packet, start)?;
+    if u16_at(fixed)? != TYPE_TSIG
+        || u16_at(fixed + 2)? != CLASS_ANY
+        || packet.get(fixed + 4..fixed + 8)? != [0; 4]
+    {
+        return None;
+    }
+
+    let rdata = fixed + 10;
+    let mut pos = skip_name(packet, rdata)?;
+    let mut time = [0u8; 8];
+    time[2..].copy_from_slice(packet.get(pos..pos + 6)?);
+    let fudge = u16_at(pos + 6)?;
+    let mac_len = usize::from(u16_at(pos + 8)?);
+    let mac = packet.get(pos + 10..pos + 10 + mac_len)?.to_vec();
+    pos += 10 + mac_len;
+    let other_len = usize::from(u16_at(pos + 4)?);
+
+    let tsig = Tsig {
+        key_name: read_name(packet, start)?,
+        algorithm: read_name(packet, rdata)?,
+        time_signed: u64::from_be_bytes(time),
+        fudge,
+        mac,
+        original_id: u16_at(pos)?,
+        error: u16_at(pos + 2)?,
+        other: packet.get(pos + 6..pos + 6 + other_len)?.to_vec(),
+    };
+    Some((start, tsig))
+}
+
+/// Offset just past a (possibly compressed) name
+fn skip_name(packet: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = usize::from(*packet.get(pos)?);
+        match len {
+            0 => return Some(pos + 1),
+            len if len & 0xc0 == 0xc0 => return Some(pos + 2).filter(|&end| end <= packet.len()),
+            len if len & 0xc0 == 0 => pos += 1 + len,
+            _ => return None,
+        }
+    }
+}
+
+/// Read a (possibly compressed) name in canonical, lowercase wire format
+fn read_name(packet: &[u8], mut pos: usize) -> Option<Vec<u8>> {
+    let mut name = Vec::new();
+    let mut jumps = 0;
+    loop {
+        let len = usize::from(*packet.get(pos)?);
+        if len & 0xc0 == 0xc0 {
+            // Compression pointers may only point backwards
+            let target =
+                usize::from(u16::from_be_bytes([packet[pos], *packet.get(pos + 1)?]) & 0x3fff);
+            jumps += 1;
+            if target >= pos || jumps > 64 {
+                return None;
+            }
+            pos = target;
+            continue;
+        }
+        if len & 0xc0 != 0 {
+            return None;
+        }
+
+        name.push(len as u8);
+        if len == 0 {
+            return Some(name);
+        }
+        let label = packet.get(pos + 1..pos + 1 + len)?;
+        name.extend(label.iter().map(u8::to_ascii_lowercase));
+        if name.len() > 255 {
+            return None;
+        }
+        pos += 1 + len;
+    }
+}
+
+/// Name of a TSIG error code (RFC 8945 §3)
+fn tsig_error(code: u16) -> String {
+    match code {
+        16 => "BADSIG (check the key secret)".to_string(),
+        17 => "BADKEY (check the key name)".to_string(),
+        18 => "BADTIME (check the system clock)".to_string(),
+        22 => "BADTRUNC".to_string(),
+        code => code.to_string(),
+    }
+}
+
+/// DNS response codes relevant to dynamic updates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rcode {
+    NoError,
+    FormErr,
+    ServFail,
+    NxDomain,
+    NotImp,
+    Refused,
+    YxDomain,
+    YxRrset,
+    NxRrset,
+    NotAuth,
+    NotZone,
+    Other(u8),
+}
+
+impl From<u8> for Rcode {
+    fn from(code: u8) -> Self {
+        match code {
+            0 => Rcode::NoError,
+            1 => Rcode::FormErr,
+            2 => Rcode::ServFail,
+            3 => Rcode::NxDomain,
+            4 => Rcode::NotImp,
+            5 => Rcode::Refused,
+            6 => Rcode::YxDomain,
+            7 => Rcode::YxRrset,
+            8 => Rcode::NxRrset,
+            9 => Rcode::NotAuth,
+            10 => Rcode::NotZone,
+            other => Rcode::Other(other),
+        }
+    }
+}
+
+impl std::fmt::Display for Rcode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rcode::NoError => write!(f, "NOERROR"),
+            Rcode::FormErr => write!(f, "FORMERR"),
+            Rcode::ServFail => write!(f, "SERVFAIL"),
+            Rcode::NxDomain => write!(f, "NXDOMAIN"),
+            Rcode::NotImp => write!(f, "NOTIMP"),
+            Rcode::Refused => write!(f, "REFUSED"),
+            Rcode::YxDomain => write!(f, "YXDOMAIN"),
+            Rcode::YxRrset => write!(f, "YXRRSET"),
+            Rcode::NxRrset => write!(f, "NXRRSET"),
+            Rcode::NotAuth => write!(f, "NOTAUTH (check the TSIG key)"),
+            Rcode::NotZone => write!(f, "NOTZONE (record is outside the zone)"),
+            Rcode::Other(code) => write!(f, "RCODE {}", code),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = b"0123456789abcdef0123456789abcdef";
+    const TIME: u64 = 1_700_000_000;
+
+    fn client() -> Rfc2136Client {
+        Rfc2136Client::new(&Rfc2136Config {
+            server: "127.0.0.1".to_string(),
+            key_name: "cddns-key".to_string(),
+            key_secret: BASE64.encode(KEY),
+            algorithm: Default::default(),
+        })
+        .unwrap()
+    }
+
+    fn message() -> UpdateMessage {
+        let mut msg = UpdateMessage::new("example.com");
+        msg.prerequisite("home.example.com", TYPE_A, CLASS_IN, 0, &[192, 0, 2, 1]);
+        msg
+    }
+
+    /// Answer a signed request the way a server would, with `rcode`
+    fn respond(client: &Rfc2136Client, request: &[u8], rcode: u8, sign: bool) -> Vec<u8> {
+        client.verify(request, None, unix_time().unwrap()).unwrap();
+        let (_, tsig) = find_tsig(request).unwrap();
+
+        // Header and zone section, nothing else
+        let zone_end = skip_name(request, 12).unwrap() + 4;
+        let mut response = request[..zone_end].to_vec();
+        response[2] |= 0x80;
+        response[3] = rcode;
+        response[6..12].fill(0);
+        if sign {
+            client
+                .sign(&mut response, Some(&tsig.mac), unix_time().unwrap())
+                .unwrap();
+        }
+        response
+    }
+
+    /// Stub server answering each request with the next rcode
+    async fn stub_server(rcodes: Vec<u8>, sign: bool) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let client = client();
+            let mut buf = [0u8; 4096];
+            for rcode in rcodes {
+                let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+                let response = respond(&client, &buf[..len], rcode, sign);
+                socket.send_to(&response, from).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn encodes_names() {
+        assert_eq!(
+            encode_name("Home.Example.com.").unwrap(),
+            b"\x04Home\x07Example\x03com\x00"
+        );
+        assert_eq!(encode_name("").unwrap(), b"\x00");
+        assert!(encode_name("a..b").is_err());
+        assert!(encode_name(&"a".repeat(64)).is_err());
+    }
+
+    #[test]
+    fn encodes_update_message() {
+        let expected = [
+            &[0x12, 0x34, 0x28, 0x00, 0, 1, 0, 1, 0, 0, 0, 0][..],
+            b"\x07example\x03com\x00",
+            &[0, 6, 0, 1],
+            b"\x04home\x07example\x03com\x00",
+            &[0, 1, 0, 1, 0, 0, 0, 0, 0, 4, 192, 0, 2, 1],
+        ]
+        .concat();
+        assert_eq!(message().encode(0x1234).unwrap(), expected);
+    }
+
+    #[test]
+    fn signs_with_tsig() {
+        let mut packet = message().encode(0x1234).unwrap();
+        let unsigned_len = packet.len();
+        let mac = client().sign(&mut packet, None, TIME).unwrap();
+
+        // HMAC-SHA256 computed independently over the message and TSIG variables
+        let expected_mac = [
+            0x5a, 0xbe, 0xe2, 0x60, 0x76, 0xc2, 0x85, 0x41, 0xf8, 0xcc, 0x26, 0xe3, 0xc4, 0x01,
+            0xc4, 0xe0, 0x70, 0x5b, 0xaf, 0xda, 0x80, 0xa6, 0xec, 0x39, 0xd9, 0xd0, 0xdb, 0xc2,
+            0xc6, 0xeb, 0xa2, 0x49,
+        ];
+        assert_eq!(mac, expected_mac);
+
+        let expected_tsig = [
+            &b"\x09cddns-key\x00"[..],
+            &[0, 250, 0, 255, 0, 0, 0, 0, 0, 61],
+            b"\x0bhmac-sha256\x00",
+            &[0x00, 0x00, 0x65, 0x53, 0xf1, 0x00, 0x01, 0x2c, 0, 32],
+            &expected_mac,
+            &[0x12, 0x34, 0, 0, 0, 0],
+        ]
+        .concat();
+        assert_eq!(packet[unsigned_len..], expected_tsig);
+        assert_eq!(packet[10..12], [0, 1]);
+    }
+
+    #[test]
+    fn verifies_signed_responses() {
+        let client = client();
+        let mut request = message().encode(0x1234).unwrap();
+        let request_mac = client.sign(&mut request, None, TIME).unwrap();
+        let zone_end = skip_name(&request, 12).unwrap() + 4;
+        let mut response = request[..zone_end].to_vec();
+        response[2] |= 0x80;
+        response[6..12].fill(0);
+        client
+            .sign(&mut response, Some(&request_mac), TIME)
+            .unwrap();
+
+        assert!(client.verify(&response, Some(&request_mac), TIME).is_ok());
+        assert!(client
+            .verify(&response, Some(&request_mac), TIME + 300)
+            .is_ok());
+
+        // Signed over a different request
+        assert!(client.verify(&response, Some(&[0; 32]), TIME).is_err());
+        // Replayed outside the allowed clock skew
+        assert!(client
+            .verify(&response, Some(&request_mac), TIME + 301)
+            .is_err());
+        // Tampered rcode
+        let mut tampered = response.clone();
+        tampered[3] = 8;
+        assert!(client.verify(&tampered, Some(&request_mac), TIME).is_err());
+        // Unsigned
+        assert!(client
+            .verify(&response[..zone_end], Some(&request_mac), TIME)
+            .is_err());
+        // Another key
+        let other = Rfc2136Client {
+            key: b"another key".to_vec(),
+            ..self::client()
+        };
+        assert!(other.verify(&response, Some(&request_mac), TIME).is_err());
+    }
+
+    #[test]
+    fn reads_compressed_names() {
+        let packet = b"\x07example\x03com\x00\x04home\xc0\x00";
+        assert_eq!(skip_name(packet, 13), Some(20));
+        assert_eq!(
+            read_name(packet, 13).unwrap(),
+            b"\x04home\x07example\x03com\x00"
+        );
+        // Pointing forwards could loop
+        assert_eq!(read_name(b"\xc0\x02\x00", 0), None);
+    }
+
+    #[tokio::test]
+    async fn accepts_signed_responses() {
+        let server = stub_server(vec![0, 8], true).await;
+        let client = client();
+        assert_eq!(
+            client.send(server, message()).await.unwrap(),
+            Rcode::NoError
+        );
+        assert_eq!(
+            client.send(server, message()).await.unwrap(),
+            Rcode::NxRrset
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_unsigned_responses() {
+        let server = stub_server(vec![0], false).await;
+        let err = client().send(server, message()).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("not signed"), "{:#}", err);
+    }
+}
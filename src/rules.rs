@@ -0,0 +1,224 @@
+use std::net::{IpAddr, Ipv4Addr};
+
+use crate::config::{
+    network_contains, parse_cron, valid_ttl, Provider, RecordConfig, ServiceConfig,
+};
+
+/// How serious a config problem is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A broken rule, with the key whose value breaks it
+#[derive(Debug, Clone)]
+pub struct Problem {
+    /// Key to point at; `None` means the record as a whole
+    pub key: Option<&'static str>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Problem {
+    fn error(key: Option<&'static str>, message: String) -> Self {
+        Self {
+            key,
+            severity: Severity::Error,
+            message,
+        }
+    }
+
+    fn warning(key: Option<&'static str>, message: String) -> Self {
+        Self {
+            key,
+            severity: Severity::Warning,
+            message,
+        }
+    }
+}
+
+/// Check one record; `is_webhook` tells whether a webhook name is configured
+pub fn check_record(
+    record: &RecordConfig,
+    service: &ServiceConfig,
+    is_webhook: impl Fn(&str) -> bool,
+) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    if record.zone.is_empty() {
+        problems.push(Problem::error(
+            Some("zone"),
+            "Record zone cannot be empty".to_string(),
+        ));
+    }
+    if record.name.is_empty() {
+        problems.push(Problem::error(
+            Some("name"),
+            "Record name cannot be empty".to_string(),
+        ));
+        return problems;
+    }
+    if !record.zone.is_empty() && !record.in_zone() {
+        problems.push(Problem::error(
+            None,
+            format!(
+                "Record {} is not inside its zone {}",
+                record.name, record.zone
+            ),
+        ));
+    }
+
+    if record.provider.is_cloudflare() && !valid_ttl(record.ttl) {
+        problems.push(Problem::error(
+            Some("ttl"),
+            format!(
+                "Record {} has TTL {}; use 1 (automatic) or 60-86400",
+                record.name, record.ttl
+            ),
+        ));
+    } else if record.proxied && record.ttl != 1 {
+        problems.push(Problem::warning(
+            Some("ttl"),
+            format!(
+                "Proxied records always use automatic TTL; ttl = {} is ignored",
+                record.ttl
+            ),
+        ));
+    }
+
+    if !record.record_type.is_address()
+        && record.content.is_none()
+        && record.content_rules.is_empty()
+    {
+        problems.push(Problem::error(
+            None,
+            format!(
+                "{} record {} needs a content template",
+                record.record_type, record.name
+            ),
+        ));
+    }
+    for rule in &record.content_rules {
+        // Parse the network to reject typos early
+        if let Err(e) = network_contains(&rule.network, IpAddr::V4(Ipv4Addr::UNSPECIFIED)) {
+            problems.push(Problem::error(Some("content_rules"), format!("{:#}", e)));
+        }
+    }
+
+    if record.proxied && !record.record_type.is_proxiable() {
+        problems.push(Problem::error(
+            Some("proxied"),
+            format!(
+                "{} record {} cannot be proxied",
+                record.record_type, record.name
+            ),
+        ));
+    }
+
+    for tag in &record.tags {
+        if !tag.contains(':') {
+            problems.push(Problem::error(
+                Some("tags"),
+                format!(
+                    "Invalid tag {:?} on {}: tags must look like \"name:value\"",
+                    tag, record.name
+                ),
+            ));
+        }
+    }
+
+    if let Err(e) = record.schedule(service) {
+        let key = if record.interval.is_some() {
+            "interval"
+        } else {
+            "cron"
+        };
+        problems.push(Problem::error(
+            Some(key),
+            format!(
+                "Invalid schedule for {}: {}",
+                record.name,
+                schedule_error(e)
+            ),
+        ));
+    } else if record.interval.is_some() && record.cron.is_some() {
+        problems.push(Problem::warning(
+            Some("cron"),
+            "cron is ignored because interval is set".to_string(),
+        ));
+    }
+
+    match record.provider {
+        Provider::Rfc2136 if !record.record_type.is_address() => {
+            problems.push(Problem::error(
+                Some("provider"),
+                format!(
+                    "The rfc2136 provider only supports A and AAAA records ({})",
+                    record.name
+                ),
+            ));
+        }
+        Provider::Webhook => match &record.webhook {
+            Some(name) if is_webhook(name) => {}
+            Some(name) => problems.push(Problem::error(
+                Some("webhook"),
+                format!(
+                    "Record {} references unknown webhook: {}",
+                    record.name, name
+                ),
+            )),
+            None => problems.push(Problem::error(
+                None,
+                format!(
+                    "Record {} uses the webhook provider but sets no webhook",
+                    record.name
+                ),
+            )),
+        },
+        _ => {}
+    }
+
+    problems
+}
+
+/// Check the schedule settings of `[service]`
+pub fn check_service(service: &ServiceConfig) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    if service.interval.is_none() {
+        if let Err(e) = parse_cron(&service.cron) {
+            problems.push(Problem::error(Some("cron"), schedule_error(e)));
+        }
+    }
+    if let Err(e) = service.timezone() {
+        problems.push(Problem::error(Some("timezone"), format!("{:#}", e)));
+    }
+    if let Some(interval) = &service.interval {
+        if let Err(e) = (ServiceConfig {
+            interval: Some(interval.clone()),
+            ..ServiceConfig::default()
+        })
+        .schedule()
+        {
+            problems.push(Problem::error(Some("interval"), format!("{:#}", e)));
+        }
+    }
+    if let Err(e) = service.jitter() {
+        problems.push(Problem::error(Some("jitter"), format!("{:#}", e)));
+    }
+    if let Err(e) = service.min_spacing() {
+        problems.push(Problem::error(Some("min_spacing"), format!("{:#}", e)));
+    }
+
+    problems
+}
+
+/// Describe a schedule error, pointing cron errors at the expected layout
+fn schedule_error(e: anyhow::Error) -> String {
+    if e.to_string().starts_with("Invalid cron expression") {
+        format!("{:#} (use 6 fields: sec min hour day month weekday)", e)
+    } else {
+        format!("{:#}", e)
+    }
+}
@@ -1,16 +1,33 @@
 use anyhow::{Context, Result};
 use chrono::{DateTime, Local, Utc};
+use futures::stream::{self, StreamExt};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::future::Future;
 use std::net::IpAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
 use std::sync::Arc;
-use tokio::sync::{broadcast, RwLock};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, Mutex, RwLock};
 use tokio_cron_scheduler::{Job, JobScheduler};
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
 
-use crate::cloudflare::{DdnsClient, UpdateResult};
-use crate::config::Config;
-use crate::ip::get_public_ip;
+use crate::config::{Config, RecordConfig, Schedule, Target};
+use crate::ip::PublicIps;
 use crate::ipc::{Command, IpcServer, LogMessage, Response, ServiceStatus};
+use crate::provider::{Providers, UpdateResult};
+use crate::updater::{Queued, Trigger, Updater};
+use crate::webhook::SentRequests;
+
+/// How long to wait for more file events before reloading, so editors can finish writing
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// How long to wait for a reconnect to settle before updating, since it changes
+/// addresses and routes in several steps
+#[cfg(target_os = "linux")]
+const NETWORK_DEBOUNCE: Duration = Duration::from_secs(2);
 
 /// Shared state for the service
 pub struct ServiceState {
@@ -18,21 +35,104 @@ pub struct ServiceState {
     pub config_path: PathBuf,
     pub last_update: Option<DateTime<Utc>>,
     pub last_result: Option<String>,
+    /// What started the last update cycle
+    pub last_trigger: Option<String>,
+    /// Update cycles that failed in a row, for the worst of the failing schedule groups
+    pub consecutive_failures: u32,
     pub current_ip: Option<IpAddr>,
-    pub next_run: Option<DateTime<Utc>>,
+    /// Next run of each schedule group
+    pub next_runs: HashMap<Schedule, DateTime<Utc>>,
+    /// When each schedule group's records were last updated
+    pub last_runs: HashMap<Schedule, DateTime<Utc>>,
     pub running: bool,
+    /// Requests the webhooks accepted, replaced on reload so edited webhooks are called again
+    pub webhook_sent: SentRequests,
 }
 
 impl ServiceState {
+    /// Recompute the next run of a schedule group, counting from now
+    pub fn refresh_next_run(&mut self, schedule: &Schedule) {
+        match schedule.next_runs(Utc::now(), 1).into_iter().next() {
+            Some(next) => {
+                self.next_runs.insert(schedule.clone(), next);
+            }
+            None => {
+                self.next_runs.remove(schedule);
+            }
+        }
+    }
+
+    /// Track the schedule groups of the current config, keeping those that didn't change
+    pub fn refresh_schedules(&mut self) {
+        let schedules = match self.config.schedules() {
+            Ok(schedules) => schedules,
+            Err(e) => {
+                warn!("Failed to compute the next run: {:#}", e);
+                return;
+            }
+        };
+        self.next_runs
+            .retain(|schedule, _| schedules.contains(schedule));
+        self.last_runs
+            .retain(|schedule, _| schedules.contains(schedule));
+        for schedule in &schedules {
+            if !self.next_runs.contains_key(schedule) {
+                self.refresh_next_run(schedule);
+            }
+        }
+    }
+
+    /// The earliest next run across all schedule groups
+    pub fn next_run(&self) -> Option<DateTime<Utc>> {
+        self.next_runs.values().min().copied()
+    }
+
+    /// The next `count` scheduled runs across all schedule groups
+    pub fn upcoming_runs(&self, count: usize) -> Vec<DateTime<Utc>> {
+        let mut runs: Vec<_> = self
+            .next_runs
+            .iter()
+            .flat_map(|(schedule, next)| {
+                std::iter::once(*next).chain(schedule.next_runs(*next, count.saturating_sub(1)))
+            })
+            .collect();
+        runs.sort();
+        runs.dedup();
+        runs.truncate(count);
+        runs
+    }
+
+    /// Record a successful update of the given schedule groups
+    pub fn mark_updated(&mut self, schedules: impl IntoIterator<Item = Schedule>) {
+        let now = Utc::now();
+        self.last_update = Some(now);
+        for schedule in schedules {
+            self.last_runs.insert(schedule, now);
+        }
+    }
+
     pub fn to_status(&self) -> ServiceStatus {
+        let mut cron = self
+            .config
+            .service
+            .schedule()
+            .map(|schedule| schedule.to_string())
+            .unwrap_or_else(|_| self.config.service.cron.clone());
+        let extra = self.next_runs.len().saturating_sub(1);
+        if extra > 0 {
+            cron = format!("{} (+{} record schedule(s))", cron, extra);
+        }
+
         ServiceStatus {
             running: self.running,
-            cron: self.config.service.cron.clone(),
+            cron,
             last_update: self.last_update.map(|t| t.to_rfc3339()),
             last_result: self.last_result.clone(),
+            last_trigger: self.last_trigger.clone(),
+            consecutive_failures: self.consecutive_failures,
             current_ip: self.current_ip.map(|ip| ip.to_string()),
             record_count: self.config.records.len(),
-            next_run: self.next_run.map(|t| t.to_rfc3339()),
+            next_run: self.next_run().map(|t| t.to_rfc3339()),
         }
     }
 }
@@ -49,10 +149,14 @@ pub async fn run(config_path: PathBuf) -> Result<()> {
     // Load initial configuration
     let config = Config::load(&config_path)?;
     info!("Loaded configuration from {}", config_path.display());
-    info!("Cron schedule: {}", config.service.cron);
-
-    // Validate cron expression early
-    let cron_expr = config.service.cron.clone();
+    let schedules = config.schedules()?;
+    for schedule in &schedules {
+        info!(
+            "Schedule: {} ({} record(s))",
+            schedule,
+            config.records_on(schedule).count()
+        );
+    }
 
     // Create shared state
     let state = Arc::new(RwLock::new(ServiceState {
@@ -60,9 +164,13 @@ pub async fn run(config_path: PathBuf) -> Result<()> {
         config_path: config_path.clone(),
         last_update: None,
         last_result: None,
+        last_trigger: None,
+        consecutive_failures: 0,
         current_ip: None,
-        next_run: None,
+        next_runs: HashMap::new(),
+        last_runs: HashMap::new(),
         running: true,
+        webhook_sent: SentRequests::default(),
     }));
 
     // Create broadcast channel for logs
@@ -71,71 +179,96 @@ pub async fn run(config_path: PathBuf) -> Result<()> {
     // Create shutdown channel
     let (shutdown_tx, mut shutdown_rx) = broadcast::channel::<()>(1);
 
+    // All update cycles run on one worker
+    let (updater, worker_handle) = Updater::start(state.clone(), log_tx.clone());
+
+    // Create scheduler with one update job per schedule group
+    let mut scheduler = JobScheduler::new()
+        .await
+        .context("Failed to create job scheduler")?;
+    state.write().await.refresh_schedules();
+    let job_context = JobContext {
+        state: state.clone(),
+        updater: updater.clone(),
+        scheduler: scheduler.clone(),
+        jobs: Arc::new(Mutex::new(HashMap::new())),
+    };
+    for schedule in schedules {
+        let job = update_job(schedule.clone(), job_context.clone())?;
+        let job_id = scheduler
+            .add(job)
+            .await
+            .context("Failed to add job to scheduler")?;
+        job_context.jobs.lock().await.insert(schedule, job_id);
+    }
+
+    let reloader = Reloader {
+        state: state.clone(),
+        scheduler: scheduler.clone(),
+        log_tx: log_tx.clone(),
+        updater: updater.clone(),
+        jobs: job_context.jobs.clone(),
+    };
+
+    // Reload on config changes and SIGHUP
+    let (reload_tx, reload_rx) = mpsc::channel(1);
+    let _watcher = match watch_config(&config_path, reload_tx.clone()) {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            warn!(
+                "Not watching the config for changes ({:#}); send SIGHUP to reload",
+                e
+            );
+            None
+        }
+    };
+    #[cfg(unix)]
+    let sighup_handle = tokio::spawn(forward_sighup(reload_tx));
+    let reload_handle = tokio::spawn(reload_on_request(reloader.clone(), reload_rx));
+
+    // Update right away on network changes; the schedule stays as a fallback
+    let watch_network = state.read().await.config.service.watch_network;
+    #[cfg(target_os = "linux")]
+    let network_handle =
+        watch_network.then(|| tokio::spawn(update_on_network_change(updater.clone())));
+    #[cfg(not(target_os = "linux"))]
+    if watch_network {
+        warn!("watch_network is only supported on Linux; relying on the schedule");
+    }
+
     // Start IPC server
     let ipc_server = IpcServer::new().await?;
     info!("IPC server listening on {}", ipc_server.path().display());
 
     // Spawn IPC handler
-    let ipc_state = state.clone();
-    let ipc_log_tx = log_tx.clone();
+    let ipc_reloader = reloader.clone();
     let ipc_shutdown_tx = shutdown_tx.clone();
     let ipc_handle = tokio::spawn(async move {
-        handle_ipc(ipc_server, ipc_state, ipc_log_tx, ipc_shutdown_tx).await;
+        handle_ipc(ipc_server, ipc_reloader, ipc_shutdown_tx).await;
     });
 
-    // Run initial update if configured
-    {
+    // Start the dyndns2 listener if configured
+    let dyndns_handle = {
         let state_guard = state.read().await;
-        if state_guard.config.service.run_on_start {
-            info!("Running initial update...");
-            drop(state_guard);
-            let result = run_update(state.clone(), Some(log_tx.clone())).await;
-            if let Err(e) = result {
-                error!("Initial update failed: {}", e);
-            }
-        }
-    }
-
-    // Create scheduler
-    let mut scheduler = JobScheduler::new()
-        .await
-        .context("Failed to create job scheduler")?;
-
-    // Clone state for the job closure
-    let job_state = state.clone();
-    let job_log_tx = log_tx.clone();
-
-    // Create the cron job
-    let job = Job::new_async(cron_expr.as_str(), move |_uuid, _lock| {
-        let state = job_state.clone();
-        let log_tx = job_log_tx.clone();
-        Box::pin(async move {
-            info!("Cron triggered: running scheduled update...");
-
-            // Reload config to pick up any changes
-            {
-                let mut state_guard = state.write().await;
-                match Config::load(&state_guard.config_path) {
-                    Ok(new_config) => {
-                        state_guard.config = new_config;
-                    }
-                    Err(e) => {
-                        warn!("Failed to reload config, using existing: {}", e);
-                    }
+        state_guard.config.service.dyndns.clone().map(|dyndns| {
+            let dyndns_state = state.clone();
+            let dyndns_log_tx = log_tx.clone();
+            let dyndns_updater = updater.clone();
+            tokio::spawn(async move {
+                if let Err(e) =
+                    crate::dyndns::serve(dyndns, dyndns_state, dyndns_log_tx, dyndns_updater).await
+                {
+                    error!("dyndns listener failed: {}", e);
                 }
-            }
-
-            if let Err(e) = run_update(state, Some(log_tx)).await {
-                error!("Scheduled update failed: {}", e);
-            }
+            })
         })
-    })
-    .context("Failed to create cron job. Check your cron expression.")?;
+    };
 
-    scheduler
-        .add(job)
-        .await
-        .context("Failed to add job to scheduler")?;
+    // Run initial update if configured
+    if state.read().await.config.service.run_on_start {
+        info!("Running initial update...");
+        updater.trigger(Trigger::Startup);
+    }
 
     // Start the scheduler
     scheduler
@@ -163,8 +296,19 @@ pub async fn run(config_path: PathBuf) -> Result<()> {
 
     info!("Shutting down service...");
 
-    // Abort IPC handler
+    // Abort IPC handler, reload tasks and dyndns listener
     ipc_handle.abort();
+    reload_handle.abort();
+    worker_handle.abort();
+    #[cfg(unix)]
+    sighup_handle.abort();
+    #[cfg(target_os = "linux")]
+    if let Some(handle) = network_handle {
+        handle.abort();
+    }
+    if let Some(handle) = dyndns_handle {
+        handle.abort();
+    }
 
     scheduler
         .shutdown()
@@ -175,18 +319,336 @@ pub async fn run(config_path: PathBuf) -> Result<()> {
     Ok(())
 }
 
-/// Handle IPC connections
-async fn handle_ipc(
-    server: IpcServer,
+/// What scheduled jobs need to run updates and schedule their next run
+#[derive(Clone)]
+struct JobContext {
     state: Arc<RwLock<ServiceState>>,
+    updater: Updater,
+    scheduler: JobScheduler,
+    /// The update job of each schedule group; holding the lock serializes reloads
+    jobs: Arc<Mutex<HashMap<Schedule, Uuid>>>,
+}
+
+impl JobContext {
+    /// Schedule the run of a cron group that follows `after`
+    async fn rearm(&self, schedule: &Schedule, after: DateTime<Utc>) {
+        let mut jobs = self.jobs.lock().await;
+        // A reload dropped the group in the meantime
+        if !jobs.contains_key(schedule) {
+            return;
+        }
+
+        let job = match cron_job(schedule.clone(), after.max(Utc::now()), self.clone()) {
+            Ok(job) => job,
+            Err(e) => {
+                error!("Failed to schedule the next run of {}: {:#}", schedule, e);
+                return;
+            }
+        };
+        match self.scheduler.add(job).await {
+            Ok(job_id) => {
+                jobs.insert(schedule.clone(), job_id);
+            }
+            Err(e) => error!("Failed to schedule the next run of {}: {:#}", schedule, e),
+        }
+    }
+}
+
+/// Create the job running scheduled updates for one schedule group
+fn update_job(schedule: Schedule, context: JobContext) -> Result<Job> {
+    match schedule {
+        Schedule::Cron { .. } => cron_job(schedule, Utc::now(), context),
+        Schedule::Interval(every) => {
+            let run = move |_uuid, _lock| {
+                let context = context.clone();
+                let schedule = schedule.clone();
+                Box::pin(scheduled_update(context.state, context.updater, schedule))
+                    as Pin<Box<dyn Future<Output = ()> + Send>>
+            };
+            Job::new_repeated_async(every, run).context("Failed to create interval job")
+        }
+    }
+}
+
+/// One-shot job for the first run of a cron group after `after`
+///
+/// The run schedules the next one before updating. The scheduler's own cron
+/// jobs keep the UTC offset they were created with, so they would run an hour
+/// off after a daylight saving change; the next run is computed in the
+/// group's time zone each time instead.
+fn cron_job(schedule: Schedule, after: DateTime<Utc>, context: JobContext) -> Result<Job> {
+    let next = schedule
+        .next_runs(after, 1)
+        .into_iter()
+        .next()
+        .context("Failed to create cron job. Check your cron expression.")?;
+    // The scheduler drops fractions of a second, which would run the job early
+    let millis = (next - Utc::now()).num_milliseconds().max(0) as u64;
+    let delay = Duration::from_secs(millis.div_ceil(1000));
+
+    let run = move |_uuid, _lock| {
+        let context = context.clone();
+        let schedule = schedule.clone();
+        Box::pin(async move {
+            context.rearm(&schedule, next).await;
+            scheduled_update(context.state, context.updater, schedule).await;
+        }) as Pin<Box<dyn Future<Output = ()> + Send>>
+    };
+    Job::new_one_shot_async(delay, run).context("Failed to create cron job")
+}
+
+/// Queue a scheduled update after the configured jitter, unless one ran too recently
+async fn scheduled_update(state: Arc<RwLock<ServiceState>>, updater: Updater, schedule: Schedule) {
+    let (jitter, min_spacing) = {
+        let mut state_guard = state.write().await;
+        state_guard.refresh_next_run(&schedule);
+        let service = &state_guard.config.service;
+        // Both were validated when the config was loaded
+        (
+            service.jitter().ok().flatten(),
+            service.min_spacing().ok().flatten(),
+        )
+    };
+
+    if let Some(jitter) = jitter {
+        let delay = Duration::from_millis(rand::random_range(0..=jitter.as_millis() as u64));
+        debug!("Delaying scheduled update by {:?}", delay);
+        tokio::time::sleep(delay).await;
+    }
+
+    let last_run = state.read().await.last_runs.get(&schedule).copied();
+    if let (Some(min_spacing), Some(last_run)) = (min_spacing, last_run) {
+        let since = (Utc::now() - last_run).to_std().unwrap_or_default();
+        if since < min_spacing {
+            info!(
+                "Skipping scheduled update ({}): the last one was {}s ago",
+                schedule,
+                since.as_secs()
+            );
+            return;
+        }
+    }
+
+    updater.trigger(Trigger::Schedule(schedule));
+}
+
+/// Swaps in new configs and keeps the scheduled jobs in step with them
+#[derive(Clone)]
+struct Reloader {
+    state: Arc<RwLock<ServiceState>>,
+    scheduler: JobScheduler,
     log_tx: broadcast::Sender<LogMessage>,
-    shutdown_tx: broadcast::Sender<()>,
-) {
+    updater: Updater,
+    /// The update job of each schedule group; holding the lock serializes reloads
+    jobs: Arc<Mutex<HashMap<Schedule, Uuid>>>,
+}
+
+impl Reloader {
+    /// Load and validate the config, then swap it in; on error the current one stays
+    async fn reload(&self) -> Result<()> {
+        let mut jobs = self.jobs.lock().await;
+        let (config_path, old_service) = {
+            let state_guard = self.state.read().await;
+            (
+                state_guard.config_path.clone(),
+                state_guard.config.service.clone(),
+            )
+        };
+        let config = Config::load(&config_path)?;
+        let schedules = config.schedules()?;
+
+        // Add jobs for new schedule groups first so updates never stop
+        let mut added = Vec::new();
+        for schedule in schedules.iter().filter(|s| !jobs.contains_key(s)) {
+            match self.add_job(schedule).await {
+                Ok(job_id) => added.push((schedule.clone(), job_id)),
+                Err(e) => {
+                    for (_, job_id) in &added {
+                        let _ = self.scheduler.remove(job_id).await;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        let dropped: Vec<_> = jobs
+            .keys()
+            .filter(|schedule| !schedules.contains(schedule))
+            .cloned()
+            .collect();
+        for schedule in &dropped {
+            match self.scheduler.remove(&jobs[schedule]).await {
+                Ok(()) => {
+                    jobs.remove(schedule);
+                }
+                // Kept so the next reload retries; its records are gone so it updates nothing
+                Err(e) => warn!("Failed to remove the job for {}: {:#}", schedule, e),
+            }
+        }
+
+        if !added.is_empty() || !dropped.is_empty() {
+            jobs.extend(added);
+            let msg = format!(
+                "Schedule changed: {}",
+                schedules
+                    .iter()
+                    .map(|schedule| schedule.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            info!("{}", msg);
+            send_log(&Some(self.log_tx.clone()), "INFO", &msg);
+        }
+
+        if config.service.dyndns != old_service.dyndns {
+            warn!("dyndns listener settings changed; restart the service to apply them");
+        }
+        if config.service.watch_network != old_service.watch_network {
+            warn!("watch_network changed; restart the service to apply it");
+        }
+
+        let mut state_guard = self.state.write().await;
+        state_guard.config = config;
+        state_guard.webhook_sent = SentRequests::default();
+        state_guard.refresh_schedules();
+        Ok(())
+    }
+
+    /// Schedule the update job for a schedule group
+    async fn add_job(&self, schedule: &Schedule) -> Result<Uuid> {
+        let context = JobContext {
+            state: self.state.clone(),
+            updater: self.updater.clone(),
+            scheduler: self.scheduler.clone(),
+            jobs: self.jobs.clone(),
+        };
+        let job = update_job(schedule.clone(), context)?;
+        self.scheduler
+            .add(job)
+            .await
+            .with_context(|| format!("Failed to schedule the job for {}", schedule))
+    }
+}
+
+/// Run an update shortly after each address or default route change
+#[cfg(target_os = "linux")]
+async fn update_on_network_change(updater: Updater) {
+    let (change_tx, mut change_rx) = mpsc::channel(1);
+    let watch = async move {
+        if let Err(e) = crate::netlink::watch(change_tx).await {
+            warn!("Stopped watching network changes: {:#}", e);
+        }
+    };
+
+    let update = async move {
+        info!("Watching for network changes");
+        while change_rx.recv().await.is_some() {
+            tokio::time::sleep(NETWORK_DEBOUNCE).await;
+            while change_rx.try_recv().is_ok() {}
+
+            info!("Network change detected, updating...");
+            updater.trigger(Trigger::Network);
+        }
+    };
+
+    tokio::join!(watch, update);
+}
+
+/// Reload the config whenever a reason arrives on the channel
+async fn reload_on_request(reloader: Reloader, mut reload_rx: mpsc::Receiver<&'static str>) {
+    while let Some(reason) = reload_rx.recv().await {
+        // Editors write files in several steps; wait for them to settle
+        tokio::time::sleep(RELOAD_DEBOUNCE).await;
+        while reload_rx.try_recv().is_ok() {}
+
+        info!("Reloading configuration ({})", reason);
+        match reloader.reload().await {
+            Ok(()) => {
+                let msg = "Configuration reloaded";
+                info!("{}", msg);
+                send_log(&Some(reloader.log_tx.clone()), "INFO", msg);
+            }
+            Err(e) => {
+                let msg = format!("Keeping the current configuration: {:#}", e);
+                error!("{}", msg);
+                send_log(&Some(reloader.log_tx.clone()), "ERROR", &msg);
+            }
+        }
+    }
+}
+
+/// Watch the config file (or every file of a config directory) for changes
+///
+/// A single file is watched through its directory, since editors and
+/// `config migrate` replace the file instead of writing to it.
+fn watch_config(
+    config_path: &Path,
+    reload_tx: mpsc::Sender<&'static str>,
+) -> Result<notify::RecommendedWatcher> {
+    let (watch_path, mode, file_name) = if config_path.is_dir() {
+        (config_path.to_path_buf(), RecursiveMode::Recursive, None)
+    } else {
+        let parent = match config_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        (parent, RecursiveMode::NonRecursive, config_path.file_name())
+    };
+    let file_name = file_name.map(|name| name.to_os_string());
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        if !matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+        ) {
+            return;
+        }
+        let relevant = event.paths.iter().any(|path| match &file_name {
+            Some(name) => path.file_name() == Some(name.as_os_str()),
+            None => path.extension().is_some_and(|ext| ext == "toml"),
+        });
+        if relevant {
+            debug!("Config change detected: {:?}", event.paths);
+            // A full channel already has a reload pending
+            let _ = reload_tx.try_send("file changed");
+        }
+    })
+    .context("Failed to create file watcher")?;
+
+    watcher
+        .watch(&watch_path, mode)
+        .with_context(|| format!("Failed to watch {}", watch_path.display()))?;
+    info!("Watching {} for config changes", watch_path.display());
+    Ok(watcher)
+}
+
+/// Request a reload on every SIGHUP
+#[cfg(unix)]
+async fn forward_sighup(reload_tx: mpsc::Sender<&'static str>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sighup = match signal(SignalKind::hangup()) {
+        Ok(sighup) => sighup,
+        Err(e) => {
+            warn!("Failed to create SIGHUP handler: {}", e);
+            return;
+        }
+    };
+    while sighup.recv().await.is_some() {
+        let _ = reload_tx.try_send("SIGHUP");
+    }
+}
+
+/// Handle IPC connections
+async fn handle_ipc(server: IpcServer, reloader: Reloader, shutdown_tx: broadcast::Sender<()>) {
     loop {
         match server.accept().await {
             Ok(mut conn) => {
-                let state = state.clone();
-                let log_tx = log_tx.clone();
+                let reloader = reloader.clone();
+                let state = reloader.state.clone();
                 let shutdown_tx = shutdown_tx.clone();
 
                 tokio::spawn(async move {
@@ -197,25 +659,45 @@ async fn handle_ipc(
                                 let state_guard = state.read().await;
                                 Response::Status(state_guard.to_status())
                             }
+                            Command::GetSchedule { count } => {
+                                let state_guard = state.read().await;
+                                Response::Schedule(
+                                    state_guard
+                                        .upcoming_runs(count)
+                                        .iter()
+                                        .map(|t| t.to_rfc3339())
+                                        .collect(),
+                                )
+                            }
                             Command::TriggerUpdate => {
-                                // Reload config
-                                {
-                                    let mut state_guard = state.write().await;
-                                    if let Ok(new_config) = Config::load(&state_guard.config_path) {
-                                        state_guard.config = new_config;
-                                    }
+                                // Pick up changes the watcher may not have seen yet
+                                if let Err(e) = reloader.reload().await {
+                                    warn!("Failed to reload config, using existing: {:#}", e);
                                 }
 
-                                // Run update
-                                let result = run_update(state.clone(), Some(log_tx.clone())).await;
-                                match result {
-                                    Ok(_) => Response::UpdateResult {
-                                        success: true,
-                                        message: "Update completed successfully".to_string(),
+                                match reloader.updater.trigger(Trigger::Manual) {
+                                    (Queued::Started, done) => match done.await {
+                                        Ok(Ok(())) => Response::UpdateResult {
+                                            success: true,
+                                            message: "Update completed successfully".to_string(),
+                                        },
+                                        Ok(Err(message)) => Response::UpdateResult {
+                                            success: false,
+                                            message,
+                                        },
+                                        Err(_) => Response::Error(
+                                            "The update worker stopped".to_string(),
+                                        ),
                                     },
-                                    Err(e) => Response::UpdateResult {
-                                        success: false,
-                                        message: e.to_string(),
+                                    (Queued::AfterRunning(running), _) => Response::UpdateQueued {
+                                        message: format!(
+                                            "An update is already running ({}); another one was queued",
+                                            running
+                                        ),
+                                    },
+                                    (Queued::Merged, _) => Response::UpdateQueued {
+                                        message: "An update is already running and another is queued"
+                                            .to_string(),
                                     },
                                 }
                             }
@@ -244,88 +726,111 @@ async fn handle_ipc(
 }
 
 /// Run a single update cycle
+///
+/// With `only`, just the records on those schedules are updated; IP lists,
+/// origins and pruning follow the service schedule. Callers go through the
+/// [`Updater`] so cycles never overlap.
 pub async fn run_update(
     state: Arc<RwLock<ServiceState>>,
     log_tx: Option<broadcast::Sender<LogMessage>>,
+    only: Option<&[Schedule]>,
 ) -> Result<()> {
-    let config = {
+    let (config, webhook_sent) = {
         let state_guard = state.read().await;
-        state_guard.config.clone()
+        (state_guard.config.clone(), state_guard.webhook_sent.clone())
     };
 
-    let client = DdnsClient::new(&config.cloudflare.api_token)?;
+    let providers = Providers::new(&config, &webhook_sent)?;
+
+    let (records, schedules): (Vec<&RecordConfig>, Vec<Schedule>) = match only {
+        Some(only) => (
+            config
+                .records
+                .iter()
+                .filter(|record| {
+                    record
+                        .schedule(&config.service)
+                        .is_ok_and(|schedule| only.contains(&schedule))
+                })
+                .collect(),
+            only.to_vec(),
+        ),
+        None => (config.records.iter().collect(), config.schedules()?),
+    };
+    let service_schedule = config.service.schedule()?;
+    let full_cycle = schedules.contains(&service_schedule);
 
     let mut success_count = 0;
     let mut error_count = 0;
     let mut last_ip = None;
-    let mut results = Vec::new();
 
-    for record in &config.records {
-        let msg = format!("Processing {} record: {}", record.record_type, record.name);
-        info!("{}", msg);
-        send_log(&log_tx, "INFO", &msg);
+    // Every record and target of the cycle uses the same detected addresses
+    let ips = match config.settings.force_ip {
+        Some(_) => PublicIps::default(),
+        None => {
+            let record_types = records.iter().map(|record| record.ip_record_type()).chain(
+                config
+                    .targets()
+                    .filter(|_| full_cycle)
+                    .map(|target| target.ip_record_type()),
+            );
+            PublicIps::detect(
+                record_types,
+                &config.settings.ipv4_url,
+                &config.settings.ipv6_url,
+            )
+            .await
+        }
+    };
 
-        // Get the IP to use
-        let ip = match config.settings.force_ip {
-            Some(ip) => {
-                let msg = format!("Using forced IP: {}", ip);
-                info!("{}", msg);
-                send_log(&log_tx, "INFO", &msg);
-                ip
-            }
-            None => {
-                match get_public_ip(
-                    record.record_type,
-                    &config.settings.ipv4_url,
-                    &config.settings.ipv6_url,
-                )
-                .await
-                {
-                    Ok(ip) => {
-                        last_ip = Some(ip);
-                        ip
-                    }
-                    Err(e) => {
-                        let msg = format!("Failed to get public IP for {}: {}", record.name, e);
-                        error!("{}", msg);
-                        send_log(&log_tx, "ERROR", &msg);
-                        error_count += 1;
-                        continue;
-                    }
-                }
+    // Zones are updated concurrently, the records of each zone in order
+    let mut zones: Vec<Vec<&RecordConfig>> = Vec::new();
+    for record in records {
+        match zones
+            .iter_mut()
+            .find(|zone| zone[0].zone.eq_ignore_ascii_case(&record.zone))
+        {
+            Some(zone) => zone.push(record),
+            None => zones.push(vec![record]),
+        }
+    }
+
+    let mut zone_updates = Vec::with_capacity(zones.len());
+    for zone in zones {
+        let (config, providers, ips, log_tx) = (&config, &providers, &ips, &log_tx);
+        zone_updates.push(async move {
+            let mut outcomes = Vec::with_capacity(zone.len());
+            for record in zone {
+                outcomes.push(update_record(config, providers, ips, record, log_tx).await);
             }
-        };
+            outcomes
+        });
+    }
+    let outcomes: Vec<Vec<RecordOutcome>> = stream::iter(zone_updates)
+        .buffer_unordered(config.settings.concurrency.max(1))
+        .collect()
+        .await;
 
-        // Update the record
-        match client.update_ddns(record, ip).await {
-            Ok(result) => {
-                let msg = match &result {
-                    UpdateResult::Created => {
-                        format!("Created new record: {} -> {}", record.name, ip)
-                    }
-                    UpdateResult::Updated { old_ip, new_ip } => {
-                        format!(
-                            "Updated record: {} ({} -> {})",
-                            record.name,
-                            old_ip
-                                .map(|ip| ip.to_string())
-                                .unwrap_or_else(|| "unknown".to_string()),
-                            new_ip
-                        )
-                    }
-                    UpdateResult::Unchanged => {
-                        format!("Record unchanged: {} already points to {}", record.name, ip)
-                    }
-                };
-                info!("{}", msg);
-                send_log(&log_tx, "INFO", &msg);
-                results.push(msg);
-                success_count += 1;
+    for outcome in outcomes.into_iter().flatten() {
+        if outcome.detected_ip.is_some() {
+            last_ip = outcome.detected_ip;
+        }
+        if outcome.success {
+            success_count += 1;
+        } else {
+            error_count += 1;
+        }
+    }
+
+    if full_cycle {
+        for target in config.targets() {
+            let outcome = update_target(&config, &providers, &ips, target, &log_tx, false).await;
+            if outcome.detected_ip.is_some() {
+                last_ip = outcome.detected_ip;
             }
-            Err(e) => {
-                let msg = format!("Failed to update {}: {}", record.name, e);
-                error!("{}", msg);
-                send_log(&log_tx, "ERROR", &msg);
+            if outcome.success {
+                success_count += 1;
+            } else {
                 error_count += 1;
             }
         }
@@ -341,7 +846,10 @@ pub async fn run_update(
     // Update state
     {
         let mut state_guard = state.write().await;
-        state_guard.last_update = Some(Utc::now());
+        // A failed cycle mustn't hold back the next try through min_spacing
+        if error_count == 0 {
+            state_guard.mark_updated(schedules);
+        }
         state_guard.last_result = Some(if error_count > 0 {
             format!("{} failed", error_count)
         } else {
@@ -356,10 +864,180 @@ pub async fn run_update(
         anyhow::bail!("{} record(s) failed to update", error_count);
     }
 
+    // Only prune after a clean cycle so a transient failure never deletes records
+    if let (true, true, Some(client)) = (
+        full_cycle,
+        config.settings.prune_orphans,
+        providers.cloudflare(),
+    ) {
+        match crate::prune::prune(client, &config, false).await {
+            Ok(0) => {}
+            Ok(count) => {
+                let msg = format!("Pruned {} orphaned record(s)", count);
+                info!("{}", msg);
+                send_log(&log_tx, "INFO", &msg);
+            }
+            Err(e) => {
+                let msg = format!("Failed to prune orphaned records: {}", e);
+                error!("{}", msg);
+                send_log(&log_tx, "ERROR", &msg);
+            }
+        }
+    }
+
     Ok(())
 }
 
-fn send_log(log_tx: &Option<broadcast::Sender<LogMessage>>, level: &str, message: &str) {
+/// Result of updating one record or target
+pub struct RecordOutcome {
+    /// Public IP detected for the record, if one was looked up
+    pub detected_ip: Option<IpAddr>,
+    pub success: bool,
+}
+
+/// Update one record, prefixing every log line with its name
+async fn update_record(
+    config: &Config,
+    providers: &Providers,
+    ips: &PublicIps,
+    record: &RecordConfig,
+    log_tx: &Option<broadcast::Sender<LogMessage>>,
+) -> RecordOutcome {
+    let log = |level: &str, msg: String| {
+        let msg = format!("[{}] {}", record.name, msg);
+        match level {
+            "ERROR" => error!("{}", msg),
+            _ => info!("{}", msg),
+        }
+        send_log(log_tx, level, &msg);
+    };
+    let mut outcome = RecordOutcome {
+        detected_ip: None,
+        success: false,
+    };
+
+    log("INFO", format!("Processing {} record", record.record_type));
+
+    // Get the IP to use
+    let ip = match config.settings.force_ip {
+        Some(ip) => {
+            log("INFO", format!("Using forced IP: {}", ip));
+            ip
+        }
+        None => match ips.get(record.ip_record_type()) {
+            Ok(ip) => {
+                outcome.detected_ip = Some(ip);
+                ip
+            }
+            Err(e) => {
+                log("ERROR", format!("Failed to get public IP: {}", e));
+                return outcome;
+            }
+        },
+    };
+
+    // Update the record
+    match providers.update_ddns(record, ip).await {
+        Ok(result) => {
+            let msg = match &result {
+                UpdateResult::Created => format!("Created new record -> {}", ip),
+                UpdateResult::Updated { old_ip, new_ip } => {
+                    format!(
+                        "Updated record ({} -> {})",
+                        old_ip
+                            .map(|ip| ip.to_string())
+                            .unwrap_or_else(|| "unknown".to_string()),
+                        new_ip
+                    )
+                }
+                UpdateResult::Unchanged => format!("Record unchanged: already points to {}", ip),
+            };
+            log("INFO", msg);
+            outcome.success = true;
+        }
+        Err(e) => log("ERROR", format!("Failed to update: {}", e)),
+    }
+    outcome
+}
+
+/// Point one IP list entry or pool origin at the current IP
+///
+/// Shared by the service and one-shot runs; `dry_run` only logs the change.
+pub async fn update_target(
+    config: &Config,
+    providers: &Providers,
+    ips: &PublicIps,
+    target: Target<'_>,
+    log_tx: &Option<broadcast::Sender<LogMessage>>,
+    dry_run: bool,
+) -> RecordOutcome {
+    let log = |level: &str, msg: String| {
+        match level {
+            "ERROR" => error!("{}", msg),
+            _ => info!("{}", msg),
+        }
+        send_log(log_tx, level, &msg);
+    };
+    let mut outcome = RecordOutcome {
+        detected_ip: None,
+        success: false,
+    };
+
+    log("INFO", format!("Processing {}", target));
+
+    let ip = match config.settings.force_ip {
+        Some(ip) => ip,
+        None => match ips.get(target.ip_record_type()) {
+            Ok(ip) => {
+                outcome.detected_ip = Some(ip);
+                ip
+            }
+            Err(e) => {
+                log(
+                    "ERROR",
+                    format!("Failed to get public IP for {}: {}", target, e),
+                );
+                return outcome;
+            }
+        },
+    };
+
+    if dry_run {
+        log(
+            "INFO",
+            format!("[DRY-RUN] Would point {} to {}", target, ip),
+        );
+        outcome.success = true;
+        return outcome;
+    }
+
+    match providers.update_target(target, ip).await {
+        Ok(result) => {
+            let msg = match &result {
+                UpdateResult::Created => format!("Added {} -> {}", target, ip),
+                UpdateResult::Updated { old_ip, new_ip } => {
+                    format!(
+                        "Updated {} ({} -> {})",
+                        target,
+                        old_ip
+                            .map(|ip| ip.to_string())
+                            .unwrap_or_else(|| "unknown".to_string()),
+                        new_ip
+                    )
+                }
+                UpdateResult::Unchanged => {
+                    format!("Unchanged: {} already points to {}", target, ip)
+                }
+            };
+            log("INFO", msg);
+            outcome.success = true;
+        }
+        Err(e) => log("ERROR", format!("Failed to update {}: {}", target, e)),
+    }
+    outcome
+}
+
+pub fn send_log(log_tx: &Option<broadcast::Sender<LogMessage>>, level: &str, message: &str) {
     if let Some(tx) = log_tx {
         let _ = tx.send(LogMessage {
             level: level.to_string(),
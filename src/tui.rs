@@ -18,10 +18,11 @@ use std::path::PathBuf;
 use std::process::Stdio;
 use tracing::Level;
 
-use crate::cloudflare::{DdnsClient, UpdateResult};
-use crate::config::{Config, RecordType};
+use crate::cloudflare::DdnsClient;
+use crate::config::{CloudflareConfig, Config, RecordType};
 use crate::ip::get_public_ip;
 use crate::ipc::{self, Command, IpcConnection, Response, ServiceStatus};
+use crate::provider::UpdateResult;
 
 /// Default config file path
 const DEFAULT_CONFIG_PATH: &str = "config.toml";
@@ -36,6 +37,8 @@ pub struct App {
     screen: Screen,
     /// API token input
     api_token: String,
+    /// Config as loaded, so saving only changes what was edited
+    loaded: Option<Config>,
     /// Zone input
     zone: String,
     /// Record name input
@@ -126,6 +129,7 @@ impl Default for App {
             mode: InputMode::Normal,
             screen: Screen::Main,
             api_token: String::new(),
+            loaded: None,
             zone: String::new(),
             record_name: String::new(),
             record_type: RecordType::A,
@@ -162,6 +166,7 @@ impl App {
     /// Load config from file if it exists
     pub fn load_config(&mut self, config: &Config) {
         self.api_token = config.cloudflare.api_token.clone();
+        self.loaded = Some(config.clone());
         if let Some(record) = config.records.first() {
             self.zone = record.zone.clone();
             self.record_name = record.name.clone();
@@ -178,18 +183,57 @@ impl App {
     }
 
     /// Build config from current app state
+    ///
+    /// The form edits the first record of the loaded config; everything else
+    /// is kept as loaded.
     fn build_config(&self) -> Result<Config> {
         let ttl: u32 = self.ttl.parse().unwrap_or(1);
-        let mut config = Config::from_args(
-            self.api_token.clone(),
-            self.zone.clone(),
-            self.record_name.clone(),
-            self.record_type,
-            self.proxied,
-            ttl,
-            None,
-        )?;
+        let Some(loaded) = &self.loaded else {
+            let mut config = Config::from_args(
+                self.api_token.clone(),
+                self.zone.clone(),
+                self.record_name.clone(),
+                self.record_type,
+                self.proxied,
+                ttl,
+                None,
+            )?;
+            config.service.cron = self.cron.clone();
+            return Ok(config);
+        };
+
+        let mut config = loaded.clone();
+        // An unchanged token keeps pointing at its file or environment variable
+        if config.cloudflare.api_token != self.api_token {
+            config.cloudflare = CloudflareConfig {
+                api_token: self.api_token.clone(),
+                ..Default::default()
+            };
+        }
         config.service.cron = self.cron.clone();
+
+        match config.records.first_mut() {
+            Some(record) => {
+                record.zone = self.zone.clone();
+                record.name = self.record_name.clone();
+                record.record_type = self.record_type;
+                record.proxied = self.proxied;
+                record.ttl = ttl;
+            }
+            None => {
+                config.records = Config::from_args(
+                    self.api_token.clone(),
+                    self.zone.clone(),
+                    self.record_name.clone(),
+                    self.record_type,
+                    self.proxied,
+                    ttl,
+                    None,
+                )?
+                .records;
+            }
+        }
+        config.validate()?;
         Ok(config)
     }
 
@@ -241,10 +285,12 @@ impl App {
         };
     }
 
+    /// Switch between A and AAAA; other loaded types are only editable in the config file
     fn toggle_record_type(&mut self) {
         self.record_type = match self.record_type {
             RecordType::A => RecordType::AAAA,
             RecordType::AAAA => RecordType::A,
+            _ => return,
         };
         self.mark_dirty();
     }
@@ -426,15 +472,13 @@ async fn run_app(
                                     _ => {}
                                 }
                             }
-                            KeyCode::Enter | KeyCode::Char('u') => {
+                            KeyCode::Enter | KeyCode::Char('u') if !app.updating => {
                                 // Trigger update
-                                if !app.updating {
-                                    if app.connected_to_service {
-                                        // Send update command to service
-                                        trigger_service_update(app).await;
-                                    } else {
-                                        perform_update(app).await;
-                                    }
+                                if app.connected_to_service {
+                                    // Send update command to service
+                                    trigger_service_update(app).await;
+                                } else {
+                                    perform_update(app).await;
                                 }
                             }
                             KeyCode::Char('i') => {
@@ -506,8 +550,8 @@ async fn detect_ip(app: &mut App) {
     app.log(Level::INFO, "Detecting public IP...");
 
     let ip_url = match app.record_type {
-        RecordType::A => crate::config::default_ipv4_url(),
         RecordType::AAAA => crate::config::default_ipv6_url(),
+        _ => crate::config::default_ipv4_url(),
     };
 
     match get_public_ip(app.record_type, &ip_url, &ip_url).await {
@@ -551,8 +595,8 @@ async fn perform_update(app: &mut App) {
         None => {
             app.log(Level::INFO, "Detecting public IP...");
             let ip_url = match app.record_type {
-                RecordType::A => crate::config::default_ipv4_url(),
                 RecordType::AAAA => crate::config::default_ipv6_url(),
+                _ => crate::config::default_ipv4_url(),
             };
             match get_public_ip(app.record_type, &ip_url, &ip_url).await {
                 Ok(ip) => {
@@ -619,6 +663,9 @@ async fn trigger_service_update(app: &mut App) {
             }
             app.refresh_service_status().await;
         }
+        Ok(Response::UpdateQueued { message }) => {
+            app.log(Level::WARN, &message);
+        }
         Ok(_) => {
             app.log(Level::ERROR, "Unexpected response from service");
         }
@@ -785,6 +832,12 @@ fn render_service_status(f: &mut Frame, app: &App, area: Rect) {
                 Span::raw("  "),
                 Span::styled("Cron: ", Style::default().fg(Color::Gray)),
                 Span::styled(&status.cron, Style::default().fg(Color::White)),
+                Span::raw("  "),
+                Span::styled("Next run: ", Style::default().fg(Color::Gray)),
+                Span::styled(
+                    status.next_run.as_deref().unwrap_or("-"),
+                    Style::default().fg(Color::White),
+                ),
             ]),
             Line::from(vec![
                 Span::styled("Last update: ", Style::default().fg(Color::Gray)),
@@ -798,6 +851,22 @@ fn render_service_status(f: &mut Frame, app: &App, area: Rect) {
                     status.last_result.as_deref().unwrap_or("-"),
                     Style::default().fg(Color::White),
                 ),
+                Span::raw("  "),
+                Span::styled("Failures: ", Style::default().fg(Color::Gray)),
+                Span::styled(
+                    status.consecutive_failures.to_string(),
+                    Style::default().fg(if status.consecutive_failures > 0 {
+                        Color::Red
+                    } else {
+                        Color::White
+                    }),
+                ),
+                Span::raw("  "),
+                Span::styled("Trigger: ", Style::default().fg(Color::Gray)),
+                Span::styled(
+                    status.last_trigger.as_deref().unwrap_or("-"),
+                    Style::default().fg(Color::White),
+                ),
             ]),
             Line::from(vec![
                 Span::styled("IP: ", Style::default().fg(Color::Gray)),
@@ -891,7 +960,10 @@ fn render_form(f: &mut Frame, app: &App, area: Rect) {
     );
 
     // Record Type (toggle)
-    let type_display = format!("{} (Space)", app.record_type);
+    let type_display = match app.record_type {
+        RecordType::A | RecordType::AAAA => format!("{} (Space)", app.record_type),
+        other => format!("{} (edit the config to change)", other),
+    };
     render_field(
         f,
         "Record Type",
@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, oneshot, Mutex, MutexGuard, Notify, RwLock};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info};
+
+use crate::config::Schedule;
+use crate::ipc::LogMessage;
+use crate::service::{run_update, send_log, ServiceState};
+
+/// Delay before the first retry of a failed cycle; it doubles with every further failure
+const RETRY_BASE: Duration = Duration::from_secs(30);
+
+/// What asked for an update cycle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trigger {
+    /// `run_on_start` when the service starts
+    Startup,
+    /// A scheduled job; only the records on that schedule are updated
+    Schedule(Schedule),
+    /// `TriggerUpdate` over IPC, e.g. from the TUI
+    Manual,
+    /// An address or route change (`watch_network`)
+    Network,
+    /// Backoff after a failed cycle; covers the same schedules, or everything for `None`
+    Retry(Option<Vec<Schedule>>),
+}
+
+impl Trigger {
+    /// Schedules whose records the trigger covers, or `None` for all records
+    fn scope(&self) -> Option<Vec<Schedule>> {
+        match self {
+            Trigger::Schedule(schedule) => Some(vec![schedule.clone()]),
+            Trigger::Retry(scope) => scope.clone(),
+            Trigger::Startup | Trigger::Manual | Trigger::Network => None,
+        }
+    }
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trigger::Startup => write!(f, "startup"),
+            Trigger::Schedule(schedule) => write!(f, "schedule {}", schedule),
+            Trigger::Manual => write!(f, "manual request"),
+            Trigger::Network => write!(f, "network change"),
+            Trigger::Retry(_) => write!(f, "retry"),
+        }
+    }
+}
+
+/// How a trigger was queued
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Queued {
+    /// No cycle was running; one starts right away
+    Started,
+    /// A cycle is running (started by the given triggers); another follows it
+    AfterRunning(String),
+    /// Merged into the cycle already waiting behind the running one
+    Merged,
+}
+
+/// Outcome of a cycle, shared by every trigger it covered
+pub type CycleResult = std::result::Result<(), String>;
+
+#[derive(Default)]
+struct Queue {
+    /// Triggers of the cycle in progress
+    running: Option<String>,
+    /// Triggers waiting for the next cycle
+    pending: Vec<Trigger>,
+    /// Callers waiting for the next cycle to finish
+    waiters: Vec<oneshot::Sender<CycleResult>>,
+}
+
+/// Runs update cycles one at a time on a single worker
+///
+/// Triggers arriving while a cycle runs are merged into one follow-up cycle,
+/// so a burst of cron fires, IPC requests and network events never updates
+/// the same records concurrently.
+#[derive(Clone)]
+pub struct Updater {
+    queue: Arc<StdMutex<Queue>>,
+    wake: Arc<Notify>,
+    /// Held while records are being updated
+    cycle: Arc<Mutex<()>>,
+}
+
+impl Updater {
+    /// Create the updater and spawn its worker
+    pub fn start(
+        state: Arc<RwLock<ServiceState>>,
+        log_tx: broadcast::Sender<LogMessage>,
+    ) -> (Self, JoinHandle<()>) {
+        let updater = Self::new();
+        let worker = tokio::spawn(updater.clone().work(state, log_tx));
+        (updater, worker)
+    }
+
+    fn new() -> Self {
+        Self {
+            queue: Arc::new(StdMutex::new(Queue::default())),
+            wake: Arc::new(Notify::new()),
+            cycle: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Queue a cycle; the receiver gets the result of the cycle covering it
+    pub fn trigger(&self, trigger: Trigger) -> (Queued, oneshot::Receiver<CycleResult>) {
+        let (done_tx, done_rx) = oneshot::channel();
+        let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+
+        let queued = if !queue.pending.is_empty() {
+            Queued::Merged
+        } else if let Some(running) = &queue.running {
+            Queued::AfterRunning(running.clone())
+        } else {
+            Queued::Started
+        };
+        match &queued {
+            Queued::Started => {}
+            Queued::AfterRunning(running) => info!(
+                "Update already running ({}); queued another for {}",
+                running, trigger
+            ),
+            Queued::Merged => info!("Update already queued; merged {} into it", trigger),
+        }
+
+        if !queue.pending.contains(&trigger) {
+            queue.pending.push(trigger);
+        }
+        queue.waiters.push(done_tx);
+        self.wake.notify_one();
+        (queued, done_rx)
+    }
+
+    /// Wait for the running cycle, then keep others out until the guard is dropped
+    ///
+    /// For updates outside the queue, such as dyndns2 pushes, that carry their own IP.
+    /// Until then `cause` counts as the running cycle for triggers arriving meanwhile.
+    pub async fn lock(&self, cause: String) -> CycleGuard<'_> {
+        let cycle = self.cycle.lock().await;
+        self.queue.lock().unwrap_or_else(|e| e.into_inner()).running = Some(cause);
+        CycleGuard {
+            queue: &self.queue,
+            _cycle: cycle,
+        }
+    }
+
+    async fn work(self, state: Arc<RwLock<ServiceState>>, log_tx: broadcast::Sender<LogMessage>) {
+        // Keyed by the scope of the failed cycle
+        let mut failing: HashMap<Option<Vec<Schedule>>, Failing> = HashMap::new();
+        loop {
+            self.wake.notified().await;
+
+            loop {
+                // Held until the cycle ends; waits for updates outside the queue
+                let cycle = self.cycle.lock().await;
+                let (triggers, waiters) = {
+                    let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+                    if queue.pending.is_empty() {
+                        queue.running = None;
+                        break;
+                    }
+                    let triggers = std::mem::take(&mut queue.pending);
+                    queue.running = Some(describe(&triggers));
+                    (triggers, std::mem::take(&mut queue.waiters))
+                };
+
+                let scope = cycle_scope(&triggers);
+                let result = self
+                    .run_cycle(&state, &log_tx, &triggers, scope.as_deref())
+                    .await;
+                drop(cycle);
+                for waiter in waiters {
+                    let _ = waiter.send(result.clone());
+                }
+
+                let mut state_guard = state.write().await;
+                if result.is_ok() {
+                    // Only scopes this cycle covered are known to work again
+                    failing.retain(|failed, entry| {
+                        let recovered = covers(scope.as_deref(), failed.as_deref());
+                        if recovered {
+                            if let Some(handle) = entry.retry.take() {
+                                handle.abort();
+                            }
+                        }
+                        !recovered
+                    });
+                } else {
+                    let entry = failing.entry(scope.clone()).or_default();
+                    if let Some(handle) = entry.retry.take() {
+                        handle.abort();
+                    }
+                    entry.failures += 1;
+                    let schedules = match &scope {
+                        Some(schedules) => schedules.clone(),
+                        None => state_guard.config.schedules().unwrap_or_default(),
+                    };
+                    entry.retry = self.schedule_retry(&schedules, scope, entry.failures, &log_tx);
+                }
+                state_guard.consecutive_failures = failing
+                    .values()
+                    .map(|failing| failing.failures)
+                    .max()
+                    .unwrap_or(0);
+            }
+        }
+    }
+
+    async fn run_cycle(
+        &self,
+        state: &Arc<RwLock<ServiceState>>,
+        log_tx: &broadcast::Sender<LogMessage>,
+        triggers: &[Trigger],
+        scope: Option<&[Schedule]>,
+    ) -> CycleResult {
+        let cause = describe(triggers);
+        let log_tx = Some(log_tx.clone());
+
+        let msg = format!("Update cycle started ({})", cause);
+        info!("{}", msg);
+        send_log(&log_tx, "INFO", &msg);
+        state.write().await.last_trigger = Some(cause.clone());
+
+        run_update(state.clone(), log_tx, scope).await.map_err(|e| {
+            error!("Update failed ({}): {}", cause, e);
+            e.to_string()
+        })
+    }
+
+    /// Queue a retry after exponential backoff, unless the schedule comes around sooner
+    fn schedule_retry(
+        &self,
+        schedules: &[Schedule],
+        scope: Option<Vec<Schedule>>,
+        failures: u32,
+        log_tx: &broadcast::Sender<LogMessage>,
+    ) -> Option<JoinHandle<()>> {
+        let period = schedules.iter().filter_map(Schedule::period).min()?;
+        let delay = RETRY_BASE.saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)));
+        if delay >= period {
+            debug!(
+                "Not retrying: the next scheduled run comes within {}",
+                humantime::format_duration(period)
+            );
+            return None;
+        }
+
+        let msg = format!(
+            "Retrying in {} ({} failed cycle(s) in a row)",
+            humantime::format_duration(delay),
+            failures
+        );
+        info!("{}", msg);
+        send_log(&Some(log_tx.clone()), "WARN", &msg);
+
+        let updater = self.clone();
+        Some(tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            updater.trigger(Trigger::Retry(scope));
+        }))
+    }
+}
+
+/// Failed cycles over one scope
+#[derive(Default)]
+struct Failing {
+    /// Cycles over the scope that failed in a row
+    failures: u32,
+    /// Pending retry of the scope
+    retry: Option<JoinHandle<()>>,
+}
+
+/// Keeps cycles out while an update outside the queue runs
+pub struct CycleGuard<'a> {
+    queue: &'a StdMutex<Queue>,
+    _cycle: MutexGuard<'a, ()>,
+}
+
+impl Drop for CycleGuard<'_> {
+    fn drop(&mut self) {
+        // Cleared before the lock is released, so the worker never sees it
+        self.queue.lock().unwrap_or_else(|e| e.into_inner()).running = None;
+    }
+}
+
+/// Schedules a cycle covers, or `None` for all records
+///
+/// Scheduled triggers only cover their own records; any other trigger covers all.
+/// The schedules are sorted, so the same records always give the same scope.
+fn cycle_scope(triggers: &[Trigger]) -> Option<Vec<Schedule>> {
+    let mut schedules = Vec::new();
+    for trigger in triggers {
+        for schedule in trigger.scope()? {
+            if !schedules.contains(&schedule) {
+                schedules.push(schedule);
+            }
+        }
+    }
+    schedules.sort_by_key(Schedule::to_string);
+    Some(schedules)
+}
+
+/// Whether a cycle over `scope` updates every record of `other`
+fn covers(scope: Option<&[Schedule]>, other: Option<&[Schedule]>) -> bool {
+    match (scope, other) {
+        (None, _) => true,
+        (Some(_), None) => false,
+        (Some(scope), Some(other)) => other.iter().all(|schedule| scope.contains(schedule)),
+    }
+}
+
+/// Triggers of a cycle, for logs and the service status
+fn describe(triggers: &[Trigger]) -> String {
+    triggers
+        .iter()
+        .map(|trigger| trigger.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn every(secs: u64) -> Schedule {
+        Schedule::Interval(Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn starts_when_idle() {
+        let updater = Updater::new();
+        let (queued, _) = updater.trigger(Trigger::Manual);
+        assert_eq!(queued, Queued::Started);
+    }
+
+    #[test]
+    fn queues_behind_the_running_cycle() {
+        let updater = Updater::new();
+        updater.queue.lock().unwrap().running = Some("startup".to_string());
+        let (queued, _) = updater.trigger(Trigger::Network);
+        assert_eq!(queued, Queued::AfterRunning("startup".to_string()));
+    }
+
+    #[test]
+    fn merges_into_the_waiting_cycle() {
+        let updater = Updater::new();
+        updater.queue.lock().unwrap().running = Some("startup".to_string());
+        updater.trigger(Trigger::Network);
+        let (queued, _) = updater.trigger(Trigger::Manual);
+        assert_eq!(queued, Queued::Merged);
+        let (queued, _) = updater.trigger(Trigger::Manual);
+        assert_eq!(queued, Queued::Merged);
+
+        let queue = updater.queue.lock().unwrap();
+        assert_eq!(queue.pending, vec![Trigger::Network, Trigger::Manual]);
+        assert_eq!(queue.waiters.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn pushes_count_as_running() {
+        let updater = Updater::new();
+        let guard = updater.lock("dyndns 192.0.2.1".to_string()).await;
+        let (queued, _) = updater.trigger(Trigger::Manual);
+        assert_eq!(queued, Queued::AfterRunning("dyndns 192.0.2.1".to_string()));
+
+        drop(guard);
+        assert_eq!(updater.queue.lock().unwrap().running, None);
+    }
+
+    #[test]
+    fn scopes_to_scheduled_triggers() {
+        let triggers = [
+            Trigger::Schedule(every(60)),
+            Trigger::Retry(Some(vec![every(300), every(60)])),
+        ];
+        assert_eq!(cycle_scope(&triggers), Some(vec![every(60), every(300)]));
+
+        let triggers = [Trigger::Schedule(every(60)), Trigger::Network];
+        assert_eq!(cycle_scope(&triggers), None);
+    }
+
+    #[test]
+    fn covers_subsets_of_its_scope() {
+        let both = [every(60), every(300)];
+        assert!(covers(None, None));
+        assert!(covers(None, Some(&both)));
+        assert!(covers(Some(&both), Some(&[every(300)])));
+        assert!(!covers(Some(&[every(60)]), Some(&both)));
+        assert!(!covers(Some(&both), None));
+    }
+}
@@ -0,0 +1,747 @@
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use toml_edit::{ImDocument, Item, TableLike};
+
+use crate::config::{
+    config_files, expand_env, migrate_document, qualify_name, Config, RecordConfig, RecordKey,
+    ServiceConfig,
+};
+use crate::rules::{self, Severity};
+
+/// A problem found in a config file
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Diagnostic {
+    file: usize,
+    span: Option<Range<usize>>,
+    severity: Severity,
+    message: String,
+}
+
+/// File index and byte span of a value
+type Location = (usize, Option<Range<usize>>);
+
+/// A config value together with where it was written
+#[derive(Clone)]
+struct Field<'a> {
+    item: &'a Item,
+    file: usize,
+    span: Option<Range<usize>>,
+    /// Where the key was written
+    key_span: Option<Range<usize>>,
+}
+
+impl Field<'_> {
+    fn as_str(&self) -> Option<&str> {
+        self.item.as_str()
+    }
+}
+
+/// A record as it will be loaded, after zone groups and defaults are applied
+struct RecordView<'a> {
+    file: usize,
+    span: Option<Range<usize>>,
+    /// Fully qualified name and where it was written
+    name: Option<(String, usize, Option<Range<usize>>)>,
+    zone: Option<String>,
+    fields: HashMap<&'a str, Field<'a>>,
+}
+
+impl<'a> RecordView<'a> {
+    /// Value of a key, falling back to `[defaults]`
+    fn get<'s>(
+        &'s self,
+        key: &str,
+        defaults: &'s HashMap<&'a str, Field<'a>>,
+    ) -> Option<&'s Field<'a>> {
+        self.fields.get(key).or_else(|| defaults.get(key))
+    }
+}
+
+/// Collects diagnostics for a set of config files
+struct Validator<'a> {
+    files: &'a [PathBuf],
+    texts: &'a [String],
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Validator<'_> {
+    fn report(
+        &mut self,
+        file: usize,
+        span: Option<Range<usize>>,
+        severity: Severity,
+        message: String,
+    ) {
+        let diagnostic = Diagnostic {
+            file,
+            span,
+            severity,
+            message,
+        };
+        // Values inherited from [defaults] would otherwise be reported once per record
+        if !self.diagnostics.contains(&diagnostic) {
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    fn error(&mut self, file: usize, span: Option<Range<usize>>, message: String) {
+        self.report(file, span, Severity::Error, message);
+    }
+
+    fn warning(&mut self, file: usize, span: Option<Range<usize>>, message: String) {
+        self.report(file, span, Severity::Warning, message);
+    }
+
+    fn location(&self, file: usize, span: &Option<Range<usize>>) -> String {
+        let path = self.files[file].display();
+        match span {
+            Some(span) => {
+                let (line, col) = line_col(&self.texts[file], span.start);
+                format!("{}:{}:{}", path, line, col)
+            }
+            None => path.to_string(),
+        }
+    }
+}
+
+/// Check a config file or directory and print every problem found
+pub fn run(path: &Path) -> Result<()> {
+    let files = config_files(path)?;
+    let mut texts = files
+        .iter()
+        .map(|file| {
+            fs::read_to_string(file)
+                .with_context(|| format!("Failed to read config file: {}", file.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let diagnostics = diagnose(path, &files, &mut texts);
+    let validator = Validator {
+        files: &files,
+        texts: &texts,
+        diagnostics: Vec::new(),
+    };
+    for diagnostic in &diagnostics {
+        print_diagnostic(&validator, diagnostic);
+    }
+
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    let warnings = diagnostics.len() - errors;
+
+    if errors > 0 {
+        anyhow::bail!(
+            "{} error(s) and {} warning(s) in {}",
+            errors,
+            warnings,
+            path.display()
+        );
+    }
+
+    if warnings > 0 {
+        println!("{}: valid with {} warning(s)", path.display(), warnings);
+    } else {
+        println!("{}: configuration is valid", path.display());
+    }
+    Ok(())
+}
+
+/// Every problem in the files of a config, in file and position order
+///
+/// `texts` are migrated in place, so spans refer to the migrated text.
+fn diagnose(path: &Path, files: &[PathBuf], texts: &mut [String]) -> Vec<Diagnostic> {
+    // Check files as loading sees them, upgraded to the current layout
+    let migrations: Vec<_> = texts.iter_mut().map(migrate).collect();
+
+    let mut validator = Validator {
+        files,
+        texts,
+        diagnostics: Vec::new(),
+    };
+
+    let mut documents = Vec::new();
+    for (index, text) in texts.iter().enumerate() {
+        match ImDocument::parse(text.as_str()) {
+            Ok(document) => documents.push((index, document)),
+            Err(e) => validator.error(index, e.span(), e.message().trim().to_string()),
+        }
+    }
+
+    for (index, migration) in migrations.into_iter().enumerate() {
+        let span = documents
+            .iter()
+            .find(|(file, _)| *file == index)
+            .and_then(|(_, document)| document.get("version"))
+            .and_then(Item::span);
+        match migration {
+            Ok(changes) if changes.is_empty() => {}
+            Ok(changes) => validator.warning(
+                index,
+                span,
+                format!(
+                    "Older config layout ({}); run 'cddns config migrate' to update it",
+                    changes.join(", ")
+                ),
+            ),
+            Err(e) => validator.error(index, span, format!("{:#}", e)),
+        }
+    }
+
+    check_documents(&mut validator, &documents);
+
+    // Anything the span checks didn't catch still fails loading
+    if !validator
+        .diagnostics
+        .iter()
+        .any(|d| d.severity == Severity::Error)
+    {
+        if let Err(e) = Config::load(path) {
+            validator.error(0, None, format!("{:#}", e));
+        }
+    }
+
+    let mut diagnostics = validator.diagnostics;
+    diagnostics.sort_by_key(|d| (d.file, d.span.as_ref().map(|s| s.start), d.severity));
+    diagnostics
+}
+
+/// Apply pending migrations to a file's text, returning what changed
+///
+/// `version` is kept as written so that every line keeps its number;
+/// migrated values show up in place of the written ones.
+fn migrate(text: &mut String) -> Result<Vec<String>> {
+    // Syntax errors are reported when the file is parsed for checking
+    let Ok(mut document) = text.parse::<toml_edit::DocumentMut>() else {
+        return Ok(Vec::new());
+    };
+    let version = document.get("version").cloned();
+
+    let changes = migrate_document(&mut document)?;
+    if !changes.is_empty() {
+        match version {
+            Some(version) => document["version"] = version,
+            None => {
+                document.remove("version");
+            }
+        }
+        *text = document.to_string();
+    }
+    Ok(changes)
+}
+
+fn check_documents(validator: &mut Validator, documents: &[(usize, ImDocument<&str>)]) {
+    let mut defaults: HashMap<&str, Field> = HashMap::new();
+    let mut webhooks = HashSet::new();
+    let mut service: HashMap<&str, Field> = HashMap::new();
+    let mut records = Vec::new();
+
+    for (file, document) in documents {
+        let file = *file;
+        let root = document.as_table();
+
+        for (_, item) in root.iter() {
+            check_env(validator, item, file);
+        }
+
+        if let Some(table) = root.get("defaults").and_then(Item::as_table_like) {
+            defaults.extend(fields(table, file));
+        }
+
+        if let Some(table) = root.get("webhooks").and_then(Item::as_table_like) {
+            webhooks.extend(table.iter().map(|(name, _)| name.to_string()));
+        }
+
+        if let Some(table) = root.get("service").and_then(Item::as_table_like) {
+            service.extend(fields(table, file));
+        }
+
+        for table in tables(root.get("records")) {
+            records.push(record_view(table, file, None));
+        }
+
+        for zone in tables(root.get("zones")) {
+            collect_zone(validator, zone, file, &mut records);
+        }
+    }
+
+    let service = check_service(validator, &service);
+
+    let mut seen: HashMap<RecordKey, Location> = HashMap::new();
+    for record in &records {
+        check_record(validator, record, &defaults, &service, &webhooks, &mut seen);
+    }
+}
+
+/// Report `${VAR}` references that loading can't expand
+fn check_env(validator: &mut Validator, item: &Item, file: usize) {
+    match item {
+        Item::Value(value) => check_env_value(validator, value, file),
+        Item::Table(table) => {
+            for (_, item) in table.iter() {
+                check_env(validator, item, file);
+            }
+        }
+        Item::ArrayOfTables(array) => {
+            for (_, item) in array.iter().flat_map(|table| table.iter()) {
+                check_env(validator, item, file);
+            }
+        }
+        Item::None => {}
+    }
+}
+
+fn check_env_value(validator: &mut Validator, value: &toml_edit::Value, file: usize) {
+    match value {
+        toml_edit::Value::String(s) if s.value().contains("${") => {
+            if let Err(e) = expand_env(s.value()) {
+                validator.error(file, value.span(), format!("{:#}", e));
+            }
+        }
+        toml_edit::Value::Array(array) => {
+            for value in array.iter() {
+                check_env_value(validator, value, file);
+            }
+        }
+        toml_edit::Value::InlineTable(table) => {
+            for (_, value) in table.iter() {
+                check_env_value(validator, value, file);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Check `[service]` with the loader's rules; returns the settings records are checked against
+fn check_service(validator: &mut Validator, service: &HashMap<&str, Field>) -> ServiceConfig {
+    let fields: Vec<(&str, &Field)> = service.iter().map(|(key, f)| (*key, f)).collect();
+    let start = fields
+        .iter()
+        .filter_map(|(_, f)| f.span.clone().map(|span| (f.file, span)))
+        .min_by_key(|(_, span)| span.start);
+    let at = start.map_or((0, None), |(file, span)| (file, Some(span)));
+
+    let Some(config) = typed::<ServiceConfig>(validator, &fields, toml::Table::new(), &at) else {
+        return ServiceConfig::default();
+    };
+
+    let problems = rules::check_service(&config);
+    for problem in &problems {
+        let (file, span) = problem
+            .key
+            .and_then(|key| service.get(key))
+            .map_or(at.clone(), |f| (f.file, f.span.clone()));
+        validator.report(file, span, problem.severity, problem.message.clone());
+    }
+
+    // Only the file tells whether cron was written or is the default
+    if service.contains_key("interval") {
+        for key in ["cron", "timezone"] {
+            if let Some(ignored) = service.get(key) {
+                validator.warning(
+                    ignored.file,
+                    ignored.span.clone(),
+                    format!("{} is ignored because interval is set", key),
+                );
+            }
+        }
+    }
+
+    // Records inherit the time zone; don't blame each of them for a broken one
+    if problems.iter().any(|p| p.severity == Severity::Error) {
+        ServiceConfig::default()
+    } else {
+        config
+    }
+}
+
+/// Expand a `[[zones]]` entry into record views
+fn collect_zone<'a>(
+    validator: &mut Validator,
+    zone: &'a dyn TableLike,
+    file: usize,
+    records: &mut Vec<RecordView<'a>>,
+) {
+    let Some(zone_name) = zone.get("name").and_then(Item::as_str) else {
+        let span = zone.get("records").and_then(Item::span);
+        validator.error(file, span, "Zone entry needs a name".to_string());
+        return;
+    };
+
+    let Some(entries) = zone.get("records").and_then(Item::as_array) else {
+        let span = zone.get("name").and_then(Item::span);
+        validator.error(
+            file,
+            span,
+            format!("Zone {} needs a records list", zone_name),
+        );
+        return;
+    };
+
+    for entry in entries.iter() {
+        let mut record = record_view(zone, file, Some(zone_name));
+        record.fields.remove("name");
+        record.fields.remove("records");
+        record.span = entry.span();
+
+        let short = match entry {
+            toml_edit::Value::String(short) => Some(short.value().as_str()),
+            toml_edit::Value::InlineTable(overrides) => {
+                record.fields.extend(fields(overrides, file));
+                overrides.get("name").and_then(|n| n.as_str())
+            }
+            _ => {
+                validator.error(
+                    file,
+                    entry.span(),
+                    "Zone records must be names or inline tables".to_string(),
+                );
+                continue;
+            }
+        };
+
+        record.name = short.map(|short| (qualify_name(short, zone_name), file, entry.span()));
+        records.push(record);
+    }
+}
+
+fn record_view<'a>(table: &'a dyn TableLike, file: usize, zone: Option<&str>) -> RecordView<'a> {
+    let fields: HashMap<&str, Field> = fields(table, file).into_iter().collect();
+
+    let name = fields
+        .get("name")
+        .and_then(|f| f.as_str().map(|n| (n.to_string(), f.file, f.span.clone())));
+    let zone = zone.map(str::to_string);
+
+    RecordView {
+        file,
+        span: fields
+            .values()
+            .filter_map(|f| f.span.clone())
+            .min_by_key(|s| s.start),
+        name,
+        zone,
+        fields,
+    }
+}
+
+/// Check a record with the loader's rules, as it will be loaded
+fn check_record<'a>(
+    validator: &mut Validator,
+    record: &RecordView<'a>,
+    defaults: &HashMap<&'a str, Field<'a>>,
+    service: &ServiceConfig,
+    webhooks: &HashSet<String>,
+    seen: &mut HashMap<RecordKey, Location>,
+) {
+    let Some((name, name_file, name_span)) = &record.name else {
+        validator.error(
+            record.file,
+            record.span.clone(),
+            "Record is missing a name".to_string(),
+        );
+        return;
+    };
+
+    // Zone groups set name and zone; everything else falls back to [defaults]
+    let mut fields: Vec<(&str, &Field)> = record.fields.iter().map(|(k, f)| (*k, f)).collect();
+    fields.extend(
+        defaults
+            .iter()
+            .filter(|(key, _)| !record.fields.contains_key(*key))
+            .map(|(key, f)| (*key, f)),
+    );
+    let mut overrides = toml::Table::new();
+    overrides.insert("name".to_string(), toml::Value::String(name.clone()));
+    if let Some(zone) = &record.zone {
+        overrides.insert("zone".to_string(), toml::Value::String(zone.clone()));
+    }
+
+    let at = (record.file, record.span.clone());
+    let Some(config) = typed::<RecordConfig>(validator, &fields, overrides, &at) else {
+        return;
+    };
+
+    for problem in rules::check_record(&config, service, |w| webhooks.contains(w)) {
+        let (file, span) = problem
+            .key
+            .and_then(|key| record.get(key, defaults))
+            .map_or((*name_file, name_span.clone()), |f| {
+                (f.file, f.span.clone())
+            });
+        validator.report(file, span, problem.severity, problem.message);
+    }
+
+    let key = config.key();
+    match seen.get(&key) {
+        Some((file, span)) => {
+            let first = validator.location(*file, span);
+            validator.error(
+                *name_file,
+                name_span.clone(),
+                format!("Duplicate {} (first defined at {})", key, first),
+            );
+        }
+        None => {
+            seen.insert(key, (*name_file, name_span.clone()));
+        }
+    }
+}
+
+/// Deserialize fields the way loading does, reporting a failure at the field that caused it
+fn typed<T: DeserializeOwned>(
+    validator: &mut Validator,
+    fields: &[(&str, &Field)],
+    overrides: toml::Table,
+    at: &Location,
+) -> Option<T> {
+    let mut table: toml::Table = fields
+        .iter()
+        .filter_map(|(key, f)| Some((key.to_string(), to_value(f.item)?)))
+        .collect();
+    table.extend(overrides);
+
+    // Round-trip through text so the error carries a span to map back
+    let text = toml::to_string(&table).ok()?;
+    match toml::from_str(&text) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            let key = e.span().and_then(|span| key_at(&text, span.start));
+            let (file, span) = key
+                .and_then(|(key, on_key)| {
+                    let (_, f) = fields.iter().find(|(name, _)| *name == key)?;
+                    let span = if on_key { &f.key_span } else { &f.span };
+                    Some((f.file, span.clone()))
+                })
+                .unwrap_or_else(|| at.clone());
+            validator.error(file, span, e.message().trim().to_string());
+            None
+        }
+    }
+}
+
+/// Top-level key whose key or value covers a byte offset, and whether it was the key
+fn key_at(text: &str, offset: usize) -> Option<(String, bool)> {
+    let document = ImDocument::parse(text).ok()?;
+    let table = document.as_table();
+    let covers =
+        |span: Option<Range<usize>>| span.is_some_and(|s| s.start <= offset && offset <= s.end);
+    // Unknown fields point at the key, bad values at the value
+    let key = table
+        .iter()
+        .filter_map(|(name, _)| table.get_key_value(name))
+        .find_map(|(key, item)| {
+            let on_key = covers(key.span());
+            (on_key || covers(item.span())).then(|| (key.get().to_string(), on_key))
+        });
+    key
+}
+
+/// Plain value of a parsed item, as the loader sees it
+fn to_value(item: &Item) -> Option<toml::Value> {
+    match item {
+        Item::None => None,
+        Item::Value(value) => Some(plain_value(value)),
+        Item::Table(table) => Some(plain_table(table)),
+        Item::ArrayOfTables(array) => Some(toml::Value::Array(
+            array.iter().map(|table| plain_table(table)).collect(),
+        )),
+    }
+}
+
+fn plain_value(value: &toml_edit::Value) -> toml::Value {
+    match value {
+        toml_edit::Value::String(s) => toml::Value::String(s.value().clone()),
+        toml_edit::Value::Integer(i) => toml::Value::Integer(*i.value()),
+        toml_edit::Value::Float(f) => toml::Value::Float(*f.value()),
+        toml_edit::Value::Boolean(b) => toml::Value::Boolean(*b.value()),
+        toml_edit::Value::Datetime(d) => toml::Value::String(d.value().to_string()),
+        toml_edit::Value::Array(array) => {
+            toml::Value::Array(array.iter().map(plain_value).collect())
+        }
+        toml_edit::Value::InlineTable(table) => plain_table(table),
+    }
+}
+
+fn plain_table(table: &dyn TableLike) -> toml::Value {
+    toml::Value::Table(
+        table
+            .iter()
+            .filter_map(|(key, item)| Some((key.to_string(), to_value(item)?)))
+            .collect(),
+    )
+}
+
+/// Fields of a table, with where their keys and values were written
+fn fields(table: &dyn TableLike, file: usize) -> Vec<(&str, Field<'_>)> {
+    table
+        .iter()
+        .map(|(key, item)| {
+            let key_span = table.get_key_value(key).and_then(|(key, _)| key.span());
+            let field = Field {
+                item,
+                file,
+                span: item.span(),
+                key_span,
+            };
+            (key, field)
+        })
+        .collect()
+}
+
+/// Tables of an array of tables or an array of inline tables
+fn tables(item: Option<&Item>) -> Vec<&dyn TableLike> {
+    match item {
+        Some(Item::ArrayOfTables(array)) => array.iter().map(|t| t as &dyn TableLike).collect(),
+        Some(Item::Value(toml_edit::Value::Array(array))) => array
+            .iter()
+            .filter_map(|v| v.as_inline_table().map(|t| t as &dyn TableLike))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn print_diagnostic(validator: &Validator, diagnostic: &Diagnostic) {
+    let label = match diagnostic.severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    };
+    println!("{}: {}", label, diagnostic.message);
+    println!(
+        "  --> {}",
+        validator.location(diagnostic.file, &diagnostic.span)
+    );
+
+    if let Some(span) = &diagnostic.span {
+        let text = &validator.texts[diagnostic.file];
+        let (line, col) = line_col(text, span.start);
+        let source = text.lines().nth(line - 1).unwrap_or_default();
+        let width = text[span.clone()]
+            .lines()
+            .next()
+            .map(|l| l.chars().count())
+            .unwrap_or(1)
+            .max(1);
+        let gutter = " ".repeat(line.to_string().len());
+        println!("{} |", gutter);
+        println!("{} | {}", line, source);
+        println!("{} | {}{}", gutter, " ".repeat(col - 1), "^".repeat(width));
+    }
+    println!();
+}
+
+/// 1-based line and column of a byte offset
+fn line_col(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let col = before
+        .rsplit('\n')
+        .next()
+        .map(|l| l.chars().count())
+        .unwrap_or(0)
+        + 1;
+    (line, col)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Line, column and message of each problem in a config file
+    fn problems(name: &str, content: &str) -> Vec<(usize, usize, String)> {
+        let dir =
+            std::env::temp_dir().join(format!("cddns-validate-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        fs::write(&path, content).unwrap();
+
+        let files = vec![path.clone()];
+        let mut texts = vec![content.to_string()];
+        diagnose(&path, &files, &mut texts)
+            .into_iter()
+            .map(|d| {
+                let (line, col) = d
+                    .span
+                    .map_or((0, 0), |span| line_col(&texts[0], span.start));
+                (line, col, d.message)
+            })
+            .collect()
+    }
+
+    const TOKEN: &str = "[cloudflare]\napi_token = \"token\"\n\n";
+
+    #[test]
+    fn points_at_defaults() {
+        let config = format!(
+            "{}[defaults]\nzone = \"example.com\"\nttl = 30\n\n\
+             [[records]]\nname = \"a.example.com\"\n\n\
+             [[records]]\nname = \"b.example.com\"\n",
+            TOKEN
+        );
+        let problems = problems("defaults", &config);
+        // Both records inherit the TTL; each is reported at the [defaults] value
+        assert_eq!(problems.len(), 2);
+        for (name, (line, col, message)) in ["a", "b"].iter().zip(&problems) {
+            assert_eq!((*line, *col), (6, 7));
+            assert_eq!(
+                message,
+                &format!(
+                    "Record {}.example.com has TTL 30; use 1 (automatic) or 60-86400",
+                    name
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn points_at_duplicates_in_zones() {
+        let config = format!(
+            "{}[[zones]]\nname = \"example.com\"\nrecords = [\"home\", \"www\", \"home\"]\n",
+            TOKEN
+        );
+        let problems = problems("zones", &config);
+        assert_eq!(problems.len(), 1);
+        let (line, col, message) = &problems[0];
+        assert_eq!((*line, *col), (6, 27));
+        assert!(
+            message
+                .starts_with("Duplicate A record home.example.com (cloudflare) (first defined at "),
+            "{}",
+            message
+        );
+        assert!(message.ends_with("config.toml:6:12)"), "{}", message);
+    }
+
+    #[test]
+    fn points_at_unknown_keys() {
+        let config = format!(
+            "{}[[records]]\nzone = \"example.com\"\nname = \"a.example.com\"\nbogus = 1\n",
+            TOKEN
+        );
+        let problems = problems("unknown", &config);
+        assert_eq!(problems.len(), 1);
+        let (line, col, message) = &problems[0];
+        assert_eq!((*line, *col), (7, 1));
+        assert!(message.starts_with("unknown field `bogus`"), "{}", message);
+    }
+
+    #[test]
+    fn points_at_unset_variables() {
+        let config = "[cloudflare]\napi_token = \"${CDDNS_TEST_UNSET_TOKEN}\"\n\n\
+                      [[records]]\nzone = \"example.com\"\nname = \"a.example.com\"\n\
+                      comment = \"at $${HOME}\"\n";
+        assert_eq!(
+            problems("env", config),
+            [(
+                2,
+                13,
+                "Environment variable CDDNS_TEST_UNSET_TOKEN is not set".to_string()
+            )]
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tracing::{debug, info};
+
+use crate::config::{RecordConfig, WebhookConfig};
+use crate::provider::UpdateResult;
+
+/// Last request each record's webhook accepted, keyed by webhook, name and type
+///
+/// Clients are rebuilt every update cycle, so the service keeps this in its
+/// state and replaces it whenever the config is reloaded.
+#[derive(Clone, Default)]
+pub struct SentRequests(Arc<Mutex<HashMap<String, String>>>);
+
+impl SentRequests {
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, String>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Client issuing the configured webhook requests
+pub struct WebhookClient {
+    client: reqwest::Client,
+    webhooks: BTreeMap<String, WebhookConfig>,
+    sent: SentRequests,
+}
+
+impl WebhookClient {
+    /// Create a new client for the configured webhooks, remembering accepted requests in `sent`
+    pub fn new(webhooks: &BTreeMap<String, WebhookConfig>, sent: &SentRequests) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            webhooks: webhooks.clone(),
+            sent: sent.clone(),
+        }
+    }
+
+    /// Call the record's webhook with the given IP
+    ///
+    /// Webhooks can't report the current value, so the call is skipped only
+    /// when the same request already succeeded since the config was loaded.
+    pub async fn update_ddns(
+        &self,
+        record_config: &RecordConfig,
+        ip: IpAddr,
+    ) -> Result<UpdateResult> {
+        let name = record_config
+            .webhook
+            .as_deref()
+            .with_context(|| format!("Record {} sets no webhook", record_config.name))?;
+        let webhook = self
+            .webhooks
+            .get(name)
+            .with_context(|| format!("Webhook not found: {}", name))?;
+
+        let method = reqwest::Method::from_bytes(webhook.method.to_uppercase().as_bytes())
+            .with_context(|| format!("Invalid HTTP method: {}", webhook.method))?;
+        let url = record_config.render(&webhook.url, ip);
+        let headers: Vec<(&String, String)> = webhook
+            .headers
+            .iter()
+            .map(|(header, value)| (header, record_config.render(value, ip)))
+            .collect();
+        let body = webhook
+            .body
+            .as_ref()
+            .map(|body| record_config.render(body, ip));
+
+        let key = format!(
+            "{} {} {}",
+            name, record_config.name, record_config.record_type
+        );
+        let fingerprint = format!("{} {} {:?} {:?}", method, url, headers, body);
+        if self.sent.lock().get(&key) == Some(&fingerprint) {
+            debug!(
+                "Webhook {} already sent {} for {}, skipping",
+                name, ip, record_config.name
+            );
+            return Ok(UpdateResult::Unchanged);
+        }
+
+        // URLs often carry the API token, so only the host is logged
+        debug!(
+            "Calling webhook {}: {} {}",
+            name,
+            method,
+            reqwest::Url::parse(&url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string))
+                .unwrap_or_default()
+        );
+
+        let mut request = self.client.request(method, &url);
+        for (header, value) in headers {
+            request = request.header(header, value);
+        }
+        if let Some(body) = body {
+            request = request.body(body);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| e.without_url())
+            .with_context(|| format!("Failed to call webhook {}", name))?;
+
+        let status = response.status();
+        let text = response
+            .text()
+            .await
+            .context("Failed to read webhook response body")?;
+
+        if !status.is_success() {
+            anyhow::bail!("Webhook {} returned {}: {}", name, status, text.trim());
+        }
+
+        if let Some(expected) = &webhook.expect_body {
+            if !text.contains(expected.as_str()) {
+                anyhow::bail!(
+                    "Webhook {} response doesn't contain {:?}: {}",
+                    name,
+                    expected,
+                    text.trim()
+                );
+            }
+        }
+
+        info!("Webhook {} updated {} -> {}", name, record_config.name, ip);
+        self.sent.lock().insert(key, fingerprint);
+
+        Ok(UpdateResult::Updated {
+            old_ip: None,
+            new_ip: ip,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::path::Path;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serve `OK` to every request on a local port, counting the calls
+    async fn server() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let mut buf = [0; 4096];
+                let _ = stream.read(&mut buf).await;
+                let _ = stream
+                    .write_all(
+                        b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nOK",
+                    )
+                    .await;
+            }
+        });
+        (format!("http://{}", address), calls)
+    }
+
+    fn config(url: &str) -> Config {
+        let content = format!(
+            "[cloudflare]\napi_token = \"token\"\n\n\
+             [webhooks.test]\nurl = \"{}/update?host={{name}}&ip={{ip}}\"\n\n\
+             [[records]]\nname = \"home.example.com\"\nzone = \"example.com\"\n\
+             provider = \"webhook\"\nwebhook = \"test\"\n",
+            url
+        );
+        Config::parse(Path::new("/nonexistent/config.toml"), &content).unwrap()
+    }
+
+    #[tokio::test]
+    async fn skips_requests_already_sent() {
+        let (url, calls) = server().await;
+        let config = config(&url);
+        let record = &config.records[0];
+        let sent = SentRequests::default();
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+
+        let client = WebhookClient::new(&config.webhooks, &sent);
+        let result = client.update_ddns(record, ip).await.unwrap();
+        assert!(matches!(result, UpdateResult::Updated { .. }));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // A client of the next cycle shares the cache
+        let client = WebhookClient::new(&config.webhooks, &sent);
+        let result = client.update_ddns(record, ip).await.unwrap();
+        assert!(matches!(result, UpdateResult::Unchanged));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // A new IP is sent again
+        let ip: IpAddr = "192.0.2.2".parse().unwrap();
+        client.update_ddns(record, ip).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // So is everything after a reload replaced the cache
+        let client = WebhookClient::new(&config.webhooks, &SentRequests::default());
+        client.update_ddns(record, ip).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}